@@ -0,0 +1,364 @@
+use std::hash::{Hash, Hasher};
+
+use crate::strains::MUTATED;
+
+// the size of the grid when none is given on the command line
+pub const DEFAULT_MAP_SIZE: (usize, usize) = (100, 100);
+
+// define the possible state a cell could be in
+#[derive(PartialEq, Eq, Copy, Clone, Default, Debug)]
+pub enum CellState {
+    Alive,
+    #[default]
+    Dead,
+    Infected,
+    // alive again after being infected, and immune to catching it for a while
+    Recovered,
+    // alive and carrying the infection, but not passing it on until it has incubated
+    Exposed,
+    // alive and protected against catching the infection, though not always
+    Vaccinated,
+    // a wall that never changes, hiding the cells either side of it from each other
+    Barrier,
+}
+
+impl CellState {
+    // every state a cell can be in, in the order of their discriminants
+    pub const ALL: [CellState; 7] = [
+        CellState::Alive,
+        CellState::Dead,
+        CellState::Infected,
+        CellState::Recovered,
+        CellState::Exposed,
+        CellState::Vaccinated,
+        CellState::Barrier,
+    ];
+}
+
+// keep track of the state of cells in a data structure outside the game engine,
+// packed into bitplanes with one bit per cell so a step can work on 64 cells at once,
+// recovered cells set a bit in both the living and infected planes, and the extra
+// plane tells apart the states those two can't hold, like exposed cells
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CellStates {
+    width: usize,
+    height: usize,
+    // every row starts on a new word, bits past the width are always clear
+    words_per_row: usize,
+    alive: Vec<u64>,
+    infected: Vec<u64>,
+    extra: Vec<u64>,
+    // the generations each cell has spent in its state, only counted while
+    // the options need it so they stay at zero for any other game
+    timers: Counts,
+    // the strain each exposed or infected cell carries, zero for every other cell
+    // and for any game that hasn't mutated
+    strains: Counts,
+}
+
+// a number kept for every cell, only stored once one of them isn't zero
+#[derive(Clone, Debug, Default)]
+struct Counts(Vec<u16>);
+
+impl Counts {
+    fn get(&self, i: usize) -> u16 {
+        self.0.get(i).copied().unwrap_or(0)
+    }
+
+    // set the number of cell i out of len cells
+    fn set(&mut self, i: usize, len: usize, value: u16) {
+        if self.0.is_empty() {
+            if value == 0 {
+                return;
+            }
+            self.0 = vec![0; len];
+        }
+        self.0[i] = value;
+    }
+
+    fn clear(&mut self) {
+        self.0 = Vec::new();
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.iter().all(|&n| n == 0)
+    }
+}
+
+// numbers that were never stored are all zero
+impl PartialEq for Counts {
+    fn eq(&self, other: &Self) -> bool {
+        if self.0.is_empty() || other.0.is_empty() {
+            self.is_zero() && other.is_zero()
+        } else {
+            self.0 == other.0
+        }
+    }
+}
+
+impl Eq for Counts {}
+
+impl Hash for Counts {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        if !self.is_zero() {
+            self.0.hash(state);
+        }
+    }
+}
+
+// everything stored about a single cell
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cell {
+    pub state: CellState,
+    pub timer: u16,
+    pub strain: u16,
+}
+
+impl CellStates {
+    // make an empty grid of the given size
+    pub fn new(width: usize, height: usize) -> Self {
+        let words_per_row = width.div_ceil(64);
+        Self {
+            width,
+            height,
+            words_per_row,
+            alive: vec![0; words_per_row * height],
+            infected: vec![0; words_per_row * height],
+            extra: vec![0; words_per_row * height],
+            timers: Counts::default(),
+            strains: Counts::default(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> CellState {
+        let (word, bit) = self.locate(x, y);
+        decode(
+            self.alive[word] >> bit,
+            self.infected[word] >> bit,
+            self.extra[word] >> bit,
+        )
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, state: CellState) {
+        let (word, bit) = self.locate(x, y);
+        let (alive, infected, extra) = encode(state);
+        self.alive[word] = (self.alive[word] & !(1 << bit)) | (alive << bit);
+        self.infected[word] = (self.infected[word] & !(1 << bit)) | (infected << bit);
+        self.extra[word] = (self.extra[word] & !(1 << bit)) | (extra << bit);
+        // a cell that's been changed starts counting again, and catches the first strain
+        self.set_timer(x, y, 0);
+        self.set_strain(x, y, 0);
+    }
+
+    pub fn timer(&self, x: usize, y: usize) -> u16 {
+        self.timers.get(y * self.width + x)
+    }
+
+    pub fn set_timer(&mut self, x: usize, y: usize, timer: u16) {
+        let len = self.width * self.height;
+        self.timers.set(y * self.width + x, len, timer);
+    }
+
+    pub fn clear_timers(&mut self) {
+        self.timers.clear();
+    }
+
+    pub fn strain(&self, x: usize, y: usize) -> u16 {
+        self.strains.get(y * self.width + x)
+    }
+
+    pub fn set_strain(&mut self, x: usize, y: usize, strain: u16) {
+        let len = self.width * self.height;
+        self.strains.set(y * self.width + x, len, strain);
+    }
+
+    pub fn clear_strains(&mut self) {
+        self.strains.clear();
+    }
+
+    // iterate over the position and strain of every cell whose infection mutated in the last step
+    pub fn mutated(&self) -> impl Iterator<Item = ((usize, usize), u16)> + '_ {
+        self.strains
+            .0
+            .iter()
+            .enumerate()
+            .filter(|&(_, &strain)| strain & MUTATED != 0)
+            .map(|(i, &strain)| ((i % self.width, i / self.width), strain))
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Cell {
+        Cell {
+            state: self.get(x, y),
+            timer: self.timer(x, y),
+            strain: self.strain(x, y),
+        }
+    }
+
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        self.set(x, y, cell.state);
+        self.set_timer(x, y, cell.timer);
+        self.set_strain(x, y, cell.strain);
+    }
+
+    // replace every cell, row by row, with cells from a grid of the same size
+    pub fn set_cells(&mut self, cells: &[Cell]) {
+        let len = self.width * self.height;
+        for (i, cell) in cells.iter().enumerate() {
+            self.timers.set(i, len, cell.timer);
+            self.strains.set(i, len, cell.strain);
+        }
+        for (y, row) in cells.chunks(self.width).enumerate() {
+            let words = y * self.words_per_row..(y + 1) * self.words_per_row;
+            let words = self.alive[words.clone()]
+                .iter_mut()
+                .zip(self.infected[words.clone()].iter_mut())
+                .zip(self.extra[words].iter_mut());
+            for (((alive, infected), extra), cells) in words.zip(row.chunks(64)) {
+                (*alive, *infected, *extra) = (0, 0, 0);
+                for (bit, cell) in cells.iter().enumerate() {
+                    let (a, i, e) = encode(cell.state);
+                    *alive |= a << bit;
+                    *infected |= i << bit;
+                    *extra |= e << bit;
+                }
+            }
+        }
+    }
+
+    // iterate over the state of every cell, row by row
+    pub fn states(&self) -> impl Iterator<Item = CellState> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| self.get(x, y)))
+    }
+
+    // make a grid of a new size, keeping the cells that still fit
+    pub fn resized(&self, width: usize, height: usize) -> Self {
+        let mut resized = Self::new(width, height);
+        for y in 0..height.min(self.height) {
+            for x in 0..width.min(self.width) {
+                resized.set_cell(x, y, self.cell(x, y));
+            }
+        }
+        resized
+    }
+
+    // roughly how much memory the cells take up
+    pub fn bytes(&self) -> usize {
+        let words = self.alive.len() + self.infected.len() + self.extra.len();
+        words * 8 + (self.timers.0.len() + self.strains.0.len()) * 2
+    }
+
+    // whether every cell is dead
+    pub fn is_empty(&self) -> bool {
+        self.alive
+            .iter()
+            .chain(&self.infected)
+            .chain(&self.extra)
+            .all(|&word| word == 0)
+    }
+
+    // whether every cell is dead or a barrier
+    pub fn is_lifeless(&self) -> bool {
+        self.alive
+            .iter()
+            .chain(&self.infected)
+            .all(|&word| word == 0)
+    }
+
+    // whether any cell is anything but alive or dead, having caught the infection at some point
+    // or being a barrier
+    pub fn has_infected(&self) -> bool {
+        self.infected
+            .iter()
+            .chain(&self.extra)
+            .any(|&word| word != 0)
+    }
+
+    // whether any cell is a barrier, the only state that sets the extra bit alone
+    pub fn has_barriers(&self) -> bool {
+        self.alive
+            .iter()
+            .zip(&self.infected)
+            .zip(&self.extra)
+            .any(|((&alive, &infected), &extra)| extra & !alive & !infected != 0)
+    }
+
+    pub fn words_per_row(&self) -> usize {
+        self.words_per_row
+    }
+
+    // the words of each plane holding a row
+    pub fn row_words(&self, y: usize) -> Planes<&[u64]> {
+        let words = y * self.words_per_row..(y + 1) * self.words_per_row;
+        (
+            &self.alive[words.clone()],
+            &self.infected[words.clone()],
+            &self.extra[words],
+        )
+    }
+
+    // swap in bitplanes laid out like this grid's, handing back the old ones
+    pub fn swap_planes(&mut self, planes: &mut Planes<Vec<u64>>) {
+        debug_assert_eq!(planes.0.len(), self.alive.len());
+        debug_assert_eq!(planes.1.len(), self.infected.len());
+        debug_assert_eq!(planes.2.len(), self.extra.len());
+        std::mem::swap(&mut self.alive, &mut planes.0);
+        std::mem::swap(&mut self.infected, &mut planes.1);
+        std::mem::swap(&mut self.extra, &mut planes.2);
+    }
+
+    // find the word and bit a cell is stored in
+    fn locate(&self, x: usize, y: usize) -> (usize, usize) {
+        debug_assert!(x < self.width && y < self.height);
+        (y * self.words_per_row + x / 64, x % 64)
+    }
+}
+
+// the living, infected and extra planes of a grid
+pub type Planes<T> = (T, T, T);
+
+// the bits a state sets in the living, infected and extra planes, every state that
+// counts as a living neighbour sets the living bit, only infected cells set the
+// infected bit alone, and barriers set neither so they're never counted
+pub fn encode(state: CellState) -> Planes<u64> {
+    match state {
+        CellState::Alive => (1, 0, 0),
+        CellState::Dead => (0, 0, 0),
+        CellState::Infected => (0, 1, 0),
+        CellState::Recovered => (1, 1, 0),
+        CellState::Exposed => (1, 0, 1),
+        CellState::Vaccinated => (1, 1, 1),
+        CellState::Barrier => (0, 0, 1),
+    }
+}
+
+// the state stored by the bits from the living, infected and extra planes
+pub fn decode(alive: u64, infected: u64, extra: u64) -> CellState {
+    match (alive & 1, infected & 1, extra & 1) {
+        (1, 1, 1) => CellState::Vaccinated,
+        (1, 0, 1) => CellState::Exposed,
+        (1, 1, _) => CellState::Recovered,
+        (1, _, _) => CellState::Alive,
+        (_, 1, _) => CellState::Infected,
+        (_, _, 1) => CellState::Barrier,
+        _ => CellState::Dead,
+    }
+}
+
+// define the starting conditions of the game
+impl Default for CellStates {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAP_SIZE.0, DEFAULT_MAP_SIZE.1)
+    }
+}
@@ -1,24 +1,20 @@
 use bevy::prelude::Component;
 use bevy_inspector_egui::Inspectable;
 
+use engine::cells::CellState;
+
 // define component used to identify the map entity
 #[derive(Component)]
 pub struct MapComponent;
 
-// define the possible state a cell could be in
-#[derive(PartialEq, Eq, Copy, Clone, Inspectable, Default, Debug)]
-pub enum CellState {
-    Alive,
-    #[default]
-    Dead,
-    Infected,
-}
-
 // define component used to identify the cell entities
 #[derive(Clone, Component, Inspectable)]
 pub struct CellComponent {
     pub coord: (usize, usize),
+    #[inspectable(ignore)]
     pub state: CellState,
+    // the strain the cell carries, if it's infected or exposed
+    pub strain: u16,
 }
 
 impl Default for CellComponent {
@@ -26,6 +22,7 @@ impl Default for CellComponent {
         Self {
             coord: (0, 0),
             state: CellState::default(),
+            strain: 0,
         }
     }
 }
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContext};
-use bevy_inspector_egui::{InspectorPlugin, RegisterInspectable, WorldInspectorPlugin};
+use bevy_inspector_egui::{RegisterInspectable, WorldInspectorPlugin};
 
 use crate::{components::*, resources::*};
 
@@ -13,7 +13,6 @@ impl Plugin for DebugPlugin {
             app.add_plugin(WorldInspectorPlugin::new())
                 .register_inspectable::<AsciiSheet>()
                 .register_inspectable::<CellComponent>()
-                .add_plugin(InspectorPlugin::<GameOptions>::new())
                 .add_system(history_panel_system);
         }
     }
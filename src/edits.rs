@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+
+use crate::{cells::Cell, simulation::Simulation};
+
+// the most edits that can be undone
+pub const EDIT_HISTORY_LENGTH: usize = 100;
+// the most memory the edits take up before forgetting the oldest, filling or clearing
+// a large grid changes so many cells that a few edits reach it
+pub const EDIT_HISTORY_BYTES: usize = 64 << 20;
+
+// a change to one cell, at its position in the world so it still applies after scrolling,
+// along with its timer and strain so undoing it puts back the whole cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellChange {
+    pub position: (i64, i64),
+    pub before: Cell,
+    pub after: Cell,
+}
+
+// changes made together, which are undone and redone as one
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Edit {
+    pub changes: Vec<CellChange>,
+    // the generation before and after the edit, clearing the grid starts counting again
+    pub generations: (u64, u64),
+}
+
+impl Edit {
+    // whether the edit changed anything
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.generations.0 == self.generations.1
+    }
+
+    // roughly how much memory the edit takes up
+    pub fn bytes(&self) -> usize {
+        self.changes.len() * std::mem::size_of::<CellChange>()
+    }
+}
+
+// the edits that can be undone, and the undone edits that can be redone
+pub struct EditHistory {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    capacity: usize,
+    max_bytes: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new(EDIT_HISTORY_LENGTH, EDIT_HISTORY_BYTES)
+    }
+}
+
+impl EditHistory {
+    // keep up to capacity edits, and only as many as fit in max_bytes,
+    // though the latest is always kept
+    pub fn new(capacity: usize, max_bytes: usize) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            capacity: capacity.max(1),
+            max_bytes,
+        }
+    }
+
+    // the number of edits that can be undone and redone
+    pub fn len(&self) -> (usize, usize) {
+        (self.undo.len(), self.redo.len())
+    }
+
+    // keep an edit so it can be undone, a new edit means nothing can be redone
+    pub fn record(&mut self, edit: Edit) {
+        if edit.is_empty() {
+            return;
+        }
+        self.undo.push(edit);
+        self.redo.clear();
+        // forget the oldest edits once the history is full
+        let mut bytes: usize = self.undo.iter().map(Edit::bytes).sum();
+        let mut forgotten = 0;
+        while self.undo.len() - forgotten > 1
+            && (self.undo.len() - forgotten > self.capacity || bytes > self.max_bytes)
+        {
+            bytes -= self.undo[forgotten].bytes();
+            forgotten += 1;
+        }
+        self.undo.drain(..forgotten);
+    }
+
+    // put back the cells the last edit changed, returning whether there was one
+    pub fn undo(&mut self, simulation: &mut Simulation) -> bool {
+        match self.undo.pop() {
+            Some(edit) => {
+                for change in edit.changes.iter().rev() {
+                    simulation.set_at(change.position, change.before);
+                }
+                simulation.generation = edit.generations.0;
+                self.redo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // make the last undone edit again, returning whether there was one
+    pub fn redo(&mut self, simulation: &mut Simulation) -> bool {
+        match self.redo.pop() {
+            Some(edit) => {
+                for change in &edit.changes {
+                    simulation.set_at(change.position, change.after);
+                }
+                simulation.generation = edit.generations.1;
+                self.undo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    // once the grid has moved on, keep the latest edits whose cells it left as they were so
+    // they can still be undone, and forget the first one it changed along with all before it
+    pub fn retain_unchanged(&mut self, simulation: &Simulation) {
+        self.redo.clear();
+        // the cells as they would be once the kept edits are undone
+        let mut undone: HashMap<(i64, i64), Cell> = HashMap::new();
+        let mut first_kept = self.undo.len();
+        for (i, edit) in self.undo.iter().enumerate().rev() {
+            let unchanged = edit.generations.0 == edit.generations.1
+                && edit.changes.iter().all(|change| {
+                    let cell = undone
+                        .get(&change.position)
+                        .copied()
+                        .unwrap_or_else(|| simulation.get_at(change.position));
+                    cell == change.after
+                });
+            if !unchanged {
+                break;
+            }
+            for change in edit.changes.iter().rev() {
+                undone.insert(change.position, change.before);
+            }
+            first_kept = i;
+        }
+        self.undo.drain(..first_kept);
+        // undoing an edit leaves the grid on the generation it has reached
+        for edit in &mut self.undo {
+            edit.generations = (simulation.generation, simulation.generation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cells::{CellState, CellStates},
+        options::{Boundary, Options},
+    };
+
+    #[test]
+    fn edits_are_undone_and_redone() {
+        let mut simulation = Simulation::default();
+        let mut history = EditHistory::default();
+        history.record(simulation.edit(1, 1, CellState::Alive));
+        history.record(simulation.edit(1, 1, CellState::Infected));
+        history.record(simulation.edit(2, 1, CellState::Alive));
+        let edited = simulation.cells.clone();
+        assert!(history.undo(&mut simulation));
+        assert!(history.undo(&mut simulation));
+        assert_eq!(simulation.cells.get(1, 1), CellState::Alive);
+        assert_eq!(simulation.cells.get(2, 1), CellState::Dead);
+        assert_eq!(history.len(), (1, 2));
+        assert!(history.redo(&mut simulation));
+        assert!(history.redo(&mut simulation));
+        assert!(!history.redo(&mut simulation));
+        assert_eq!(simulation.cells, edited);
+        // a new edit can't be followed by the undone ones
+        history.undo(&mut simulation);
+        history.record(simulation.edit(5, 5, CellState::Alive));
+        assert_eq!(history.len(), (3, 0));
+    }
+
+    #[test]
+    fn clearing_the_grid_is_undone() {
+        for boundary in [Boundary::Dead, Boundary::Unbounded] {
+            let mut cells = CellStates::new(10, 10);
+            cells.set(3, 4, CellState::Alive);
+            cells.set(9, 9, CellState::Infected);
+            let options = Options {
+                boundary,
+                ..Options::default()
+            };
+            let mut simulation = Simulation::new(cells.clone(), options);
+            simulation.generation = 12;
+            let mut history = EditHistory::default();
+            history.record(simulation.clear());
+            assert!(simulation.cells.is_empty());
+            assert_eq!(simulation.generation, 0);
+            history.undo(&mut simulation);
+            assert_eq!(simulation.cells, cells);
+            assert_eq!(simulation.generation, 12);
+            history.redo(&mut simulation);
+            assert!(simulation.cells.is_empty());
+        }
+    }
+
+    #[test]
+    fn undoing_puts_back_the_whole_cell() {
+        for boundary in [Boundary::Dead, Boundary::Unbounded] {
+            let mut cells = CellStates::new(10, 10);
+            cells.set(3, 4, CellState::Infected);
+            cells.set_timer(3, 4, 2);
+            cells.set_strain(3, 4, 1);
+            let options = Options {
+                boundary,
+                ..Options::default()
+            };
+            let mut simulation = Simulation::new(cells.clone(), options);
+            // an edit that leaves the state alone doesn't touch the timer or strain
+            assert!(simulation.edit(3, 4, CellState::Infected).is_empty());
+            assert_eq!(simulation.cells, cells);
+            let mut history = EditHistory::default();
+            history.record(simulation.edit(3, 4, CellState::Alive));
+            history.undo(&mut simulation);
+            assert_eq!(simulation.cells, cells);
+            history.record(simulation.clear());
+            history.undo(&mut simulation);
+            assert_eq!(simulation.cells, cells);
+            assert_eq!(simulation.get_at((3, 4)), cells.cell(3, 4));
+        }
+    }
+
+    #[test]
+    fn edits_follow_the_world_when_scrolled() {
+        let options = Options {
+            boundary: Boundary::Unbounded,
+            ..Options::default()
+        };
+        let mut simulation = Simulation::new(CellStates::new(10, 10), options);
+        let mut history = EditHistory::default();
+        history.record(simulation.edit(0, 0, CellState::Alive));
+        simulation.scroll_to(-3, 0);
+        assert_eq!(simulation.cells.get(3, 0), CellState::Alive);
+        history.undo(&mut simulation);
+        assert_eq!(simulation.cells.get(3, 0), CellState::Dead);
+        assert_eq!(simulation.world.get(0, 0), CellState::Dead);
+    }
+
+    #[test]
+    fn edits_are_undone_after_a_step() {
+        let options = Options {
+            virulence: 0,
+            ..Options::default()
+        };
+        let mut simulation = Simulation::new(CellStates::new(10, 10), options);
+        let mut history = EditHistory::default();
+        // a block stays as it is, a lone cell dies
+        for (x, y) in [(1, 1), (2, 1), (1, 2), (2, 2)] {
+            history.record(simulation.edit(x, y, CellState::Alive));
+        }
+        history.record(simulation.edit(7, 7, CellState::Alive));
+        history.record(simulation.edit(2, 2, CellState::Dead));
+        history.record(simulation.edit(2, 2, CellState::Alive));
+        simulation.step();
+        history.retain_unchanged(&simulation);
+        // only the edits after the lone cell can still be undone
+        assert_eq!(history.len(), (2, 0));
+        assert!(history.undo(&mut simulation));
+        assert_eq!(simulation.cells.get(2, 2), CellState::Dead);
+        assert_eq!(simulation.generation, 1);
+        assert!(history.undo(&mut simulation));
+        assert_eq!(simulation.cells.get(2, 2), CellState::Alive);
+        assert!(!history.undo(&mut simulation));
+        // clearing the grid can't be undone once another generation has been played
+        history.record(simulation.clear());
+        simulation.step();
+        history.retain_unchanged(&simulation);
+        assert_eq!(history.len(), (0, 0));
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut simulation = Simulation::default();
+        let mut history = EditHistory::new(2, EDIT_HISTORY_BYTES);
+        for x in 0..4 {
+            history.record(simulation.edit(x, 0, CellState::Alive));
+        }
+        assert_eq!(history.len(), (2, 0));
+        // edits that change nothing aren't kept
+        history.record(simulation.edit(0, 0, CellState::Alive));
+        assert_eq!(history.len(), (2, 0));
+        // large edits are forgotten once they fill the memory, though the latest is kept
+        let mut cells = CellStates::new(10, 10);
+        for x in 0..10 {
+            cells.set(x, 5, CellState::Alive);
+        }
+        let mut simulation = Simulation::default();
+        let fill = simulation.fill(&cells);
+        let mut history = EditHistory::new(EDIT_HISTORY_LENGTH, fill.bytes() * 2);
+        history.record(fill);
+        history.record(simulation.edit(0, 0, CellState::Alive));
+        history.record(simulation.clear());
+        assert_eq!(history.len(), (2, 0));
+        let mut history = EditHistory::new(EDIT_HISTORY_LENGTH, 0);
+        history.record(simulation.fill(&cells));
+        assert_eq!(history.len(), (1, 0));
+    }
+}
@@ -0,0 +1,192 @@
+use std::{collections::VecDeque, fmt};
+
+use crate::simulation::Simulation;
+
+// the longest cycle that can be looked for, and the default
+pub const MAX_PERIOD: usize = 1000;
+pub const DEFAULT_MAX_PERIOD: usize = 30;
+
+// define the ways a game can come to an end by itself
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Ending {
+    // every cell has died
+    Extinct,
+    // every generation is the same as the last
+    Steady,
+    // the generations repeat after the given number of steps
+    Oscillating(u64),
+}
+
+impl fmt::Display for Ending {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Extinct => write!(f, "Every cell has died"),
+            Self::Steady => write!(f, "The grid stopped changing"),
+            Self::Oscillating(period) => {
+                write!(f, "The grid repeats itself every {} generations", period)
+            }
+        }
+    }
+}
+
+// watch the generations go by, remembering a hash of each recent one to spot repeats
+#[derive(Default)]
+pub struct EndDetector {
+    // the generation and state hash of the most recent generations seen, oldest first
+    hashes: VecDeque<(u64, u64)>,
+}
+
+impl EndDetector {
+    // remember the current generation, an edited generation replaces the one seen before,
+    // and a jump forward or back starts again as the generations in between weren't seen
+    pub fn observe(&mut self, simulation: &Simulation) {
+        let generation = simulation.generation;
+        match self.hashes.back() {
+            Some(&(last, _)) if last == generation => {
+                self.hashes.pop_back();
+            }
+            Some(&(last, _)) if last + 1 != generation => self.hashes.clear(),
+            _ => (),
+        }
+        self.hashes.push_back((generation, simulation.state_hash()));
+        while self.hashes.len() > MAX_PERIOD + 1 {
+            self.hashes.pop_front();
+        }
+    }
+
+    // how the game has ended as of the last generation seen, if it has,
+    // only looking for cycles of up to max_period generations, and only once
+    // nothing is left to chance, as a grid seen before could otherwise roll differently
+    pub fn ending(&self, simulation: &Simulation, max_period: usize) -> Option<Ending> {
+        if simulation.is_extinct() {
+            return Some(Ending::Extinct);
+        }
+        if !simulation.deterministic() {
+            return None;
+        }
+        let &(generation, hash) = self.hashes.back()?;
+        // the most recent match gives the shortest period
+        let period = self
+            .hashes
+            .iter()
+            .rev()
+            .skip(1)
+            .take(max_period)
+            .find(|&&(_, earlier)| earlier == hash)
+            .map(|&(earlier, _)| generation - earlier)?;
+        Some(if period == 1 {
+            Ending::Steady
+        } else {
+            Ending::Oscillating(period)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cells::{CellState, CellStates},
+        options::{Boundary, Infection, Options},
+    };
+
+    fn simulation(alive: &[(usize, usize)], boundary: Boundary) -> Simulation {
+        let mut cells = CellStates::new(12, 12);
+        for &(x, y) in alive {
+            cells.set(x, y, CellState::Alive);
+        }
+        let options = Options {
+            virulence: 0,
+            boundary,
+            ..Options::default()
+        };
+        Simulation::new(cells, options)
+    }
+
+    // step the simulation, returning the ending and the generation it was found at
+    fn play(simulation: &mut Simulation, max_period: usize) -> Option<(Ending, u64)> {
+        let mut detector = EndDetector::default();
+        detector.observe(simulation);
+        for _ in 0..100 {
+            simulation.step();
+            detector.observe(simulation);
+            if let Some(ending) = detector.ending(simulation, max_period) {
+                return Some((ending, simulation.generation));
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn endings_are_detected() {
+        for boundary in [Boundary::Dead, Boundary::Unbounded] {
+            // a lone cell dies straight away
+            let mut lone = simulation(&[(5, 5)], boundary);
+            assert_eq!(play(&mut lone, 30), Some((Ending::Extinct, 1)));
+            // a block never changes
+            let mut block = simulation(&[(5, 5), (6, 5), (5, 6), (6, 6)], boundary);
+            assert_eq!(play(&mut block, 30), Some((Ending::Steady, 1)));
+            // a blinker flips back every two generations
+            let mut blinker = simulation(&[(4, 5), (5, 5), (6, 5)], boundary);
+            assert_eq!(play(&mut blinker, 30), Some((Ending::Oscillating(2), 2)));
+        }
+    }
+
+    #[test]
+    fn long_cycles_are_ignored() {
+        let mut blinker = simulation(&[(4, 5), (5, 5), (6, 5)], Boundary::Dead);
+        assert_eq!(play(&mut blinker, 1), None);
+    }
+
+    #[test]
+    fn games_left_to_chance_only_end_in_extinction() {
+        // a block beside an infected cell can go a few generations without catching it
+        let mut block = simulation(&[(5, 5), (6, 5), (5, 6), (6, 6)], Boundary::Dead);
+        block.set(7, 5, CellState::Infected);
+        block.options.infection = Infection::Chance {
+            probability: 0.05,
+            seed: 3,
+        };
+        // so it only ends once the infection has run its course, not when it first looks settled
+        assert!(matches!(
+            play(&mut block, 30),
+            Some((Ending::Extinct, generation)) if generation > 1
+        ));
+    }
+
+    #[test]
+    fn gliders_on_an_endless_plane_never_end() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut simulation = simulation(&glider, Boundary::Unbounded);
+        assert_eq!(play(&mut simulation, 30), None);
+    }
+
+    #[test]
+    fn edits_replace_the_generation_seen() {
+        let mut simulation = simulation(&[(5, 5), (6, 5), (5, 6), (6, 6)], Boundary::Dead);
+        let mut detector = EndDetector::default();
+        detector.observe(&simulation);
+        simulation.step();
+        detector.observe(&simulation);
+        assert_eq!(detector.ending(&simulation, 30), Some(Ending::Steady));
+        // turning the block into a blinker before the next step means it hasn't settled
+        simulation.set(5, 6, CellState::Dead);
+        simulation.set(6, 6, CellState::Dead);
+        simulation.set(7, 5, CellState::Alive);
+        detector.observe(&simulation);
+        assert_eq!(detector.ending(&simulation, 30), None);
+        simulation.step();
+        detector.observe(&simulation);
+        assert_eq!(detector.ending(&simulation, 30), None);
+        simulation.step();
+        detector.observe(&simulation);
+        assert_eq!(
+            detector.ending(&simulation, 30),
+            Some(Ending::Oscillating(2))
+        );
+        // going back in time starts again
+        simulation.generation = 1;
+        detector.observe(&simulation);
+        assert_eq!(detector.ending(&simulation, 30), None);
+    }
+}
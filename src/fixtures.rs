@@ -0,0 +1,29 @@
+// repeatable grids shared by the tests
+use std::ops::Range;
+
+use crate::cells::{CellState, CellStates};
+
+// fill a region with states picked from `states` by a seeded lcg
+pub fn scatter(
+    cells: &mut CellStates,
+    xs: Range<usize>,
+    ys: Range<usize>,
+    mut seed: u64,
+    states: &[CellState],
+) {
+    for y in ys {
+        for x in xs.clone() {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            cells.set(x, y, states[(seed >> 33) as usize % states.len()]);
+        }
+    }
+}
+
+// fill a grid with a repeatable mix of living, infected and dead cells
+pub fn scattered(width: usize, height: usize) -> CellStates {
+    let mut cells = CellStates::new(width, height);
+    scatter(&mut cells, 0..width, 0..height, 12345, &CellState::ALL[..3]);
+    cells
+}
@@ -1,11 +1,17 @@
 use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use engine::{
+    cells::CellState, edits::EditHistory, ending::EndDetector, simulation::Simulation,
+    timeline::Timeline,
+};
 
 use crate::{
-    components::{CellComponent, CellState, MapComponent},
-    grid::{update_sprite, MAP_SIZE},
+    components::{CellComponent, MapComponent},
+    grid::{sync_sprites, update_sprite},
     resources::{
-        CellStates, CursorPosition, GameOptions, GameTimer, History, Options, PrevCursorPosition,
-        Rules,
+        CursorPosition, FastForwardError, FastForwardGenerations, GameOptions, GameTimer, History,
+        PrevCursorPosition, RemainingGenerations, RunGenerations, SteppedGenerations, VaccineBrush,
     },
     GameState,
 };
@@ -18,18 +24,52 @@ impl Plugin for GenerationPlugin {
         // insert various functions into the app
         app.insert_resource(GameTimer::default())
             .insert_resource(History::default())
+            .insert_resource(FastForwardGenerations::default())
+            .insert_resource(FastForwardError::default())
+            .insert_resource(SteppedGenerations::default())
+            .insert_resource(RemainingGenerations::default())
+            .insert_resource(RunGenerations::default())
+            .insert_resource(Timeline::default())
+            .insert_resource(EditHistory::default())
+            .insert_resource(EndDetector::default())
+            .add_event::<FastForward>()
+            .add_event::<EditCommand>()
+            .add_system(options_sync_system)
+            .add_system(fast_forward_system)
+            .add_system(timeline_panel_system)
             .add_system_set(
-                SystemSet::on_update(GameState::Paused).with_system(user_drawing_system),
+                SystemSet::on_update(GameState::Paused)
+                    .with_system(user_drawing_system)
+                    .with_system(vaccine_brush_system)
+                    .with_system(edit_history_system),
             )
             .add_system_set(SystemSet::on_update(GameState::Play).with_system(generation_system));
     }
 }
 
+// the most generations that can be skipped at once, with hashlife and one step at a time,
+// a hashlife jump runs within a single frame so it's kept short enough not to freeze the game
+pub const MAX_FAST_FORWARD: u64 = 1 << 20;
+pub const MAX_STEPPED_FAST_FORWARD: u64 = 1000;
+// the most cells worked out in a frame when fast-forwarding one step at a time, so the
+// generations of a large grid are spread over several frames
+const STEPPED_CELLS_PER_FRAME: usize = 1 << 19;
+
+// event sent to skip the given number of generations
+pub struct FastForward(pub u64);
+
+// event sent to undo or redo the last edit to the grid
+pub enum EditCommand {
+    Undo,
+    Redo,
+}
+
 fn user_drawing_system(
     position: Res<CursorPosition>,
     prev_position: Res<PrevCursorPosition>,
     keyboard: Res<Input<KeyCode>>,
-    mut cell_states: ResMut<CellStates>,
+    mut simulation: ResMut<Simulation>,
+    mut history: ResMut<EditHistory>,
     map_query: Query<&mut Children, With<MapComponent>>,
     mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
 ) {
@@ -41,17 +81,7 @@ fn user_drawing_system(
         let (mut cell, mut sprite) = cell_query.get_mut(child).unwrap();
         // return the previously selected cell's colour back to its original colour
         if (prev_position.0, prev_position.1) == cell.coord {
-            match cell.state {
-                CellState::Dead => {
-                    sprite.color = Color::rgb(0., 0., 0.);
-                }
-                CellState::Alive => {
-                    sprite.color = Color::rgb(255., 255., 255.);
-                }
-                CellState::Infected => {
-                    sprite.color = Color::rgb(0., 255., 0.);
-                }
-            }
+            update_sprite(&cell, &mut sprite);
         }
         // check if the cell is selected
         if cell.coord == (position.0, position.1) {
@@ -60,94 +90,218 @@ fn user_drawing_system(
             if keyboard.just_released(KeyCode::Space) {
                 match cell.state {
                     CellState::Dead => cell.state = CellState::Alive,
-                    CellState::Alive => cell.state = CellState::Infected,
-                    CellState::Infected => cell.state = CellState::Dead,
+                    CellState::Alive => cell.state = CellState::Exposed,
+                    CellState::Exposed => cell.state = CellState::Infected,
+                    CellState::Infected => cell.state = CellState::Recovered,
+                    CellState::Recovered => cell.state = CellState::Vaccinated,
+                    CellState::Vaccinated => cell.state = CellState::Dead,
+                    CellState::Barrier => cell.state = CellState::Dead,
                 }
-                update_sprite(cell.state, &mut sprite);
-                cell_states.0[cell.coord.0][cell.coord.1] = cell.state;
+                // a painted cell carries the first strain
+                cell.strain = 0;
+                update_sprite(&cell, &mut sprite);
+                history.record(simulation.edit(cell.coord.0, cell.coord.1, cell.state));
+            }
+            // build a barrier on the cell, or knock one down
+            if keyboard.just_released(KeyCode::B) {
+                cell.state = if cell.state == CellState::Barrier {
+                    CellState::Dead
+                } else {
+                    CellState::Barrier
+                };
+                cell.strain = 0;
+                update_sprite(&cell, &mut sprite);
+                history.record(simulation.edit(cell.coord.0, cell.coord.1, cell.state));
             }
         }
     }
 }
 
+fn vaccine_brush_system(
+    position: Res<CursorPosition>,
+    keyboard: Res<Input<KeyCode>>,
+    brush: Res<VaccineBrush>,
+    mut simulation: ResMut<Simulation>,
+    mut history: ResMut<EditHistory>,
+    map_query: Query<&Children, With<MapComponent>>,
+    mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
+) {
+    if keyboard.just_released(KeyCode::V) {
+        history.record(simulation.vaccinate(position.0, position.1, brush.0));
+        sync_sprites(map_query.single(), &mut cell_query, &simulation.cells);
+    }
+}
+
+fn edit_history_system(
+    keyboard: Res<Input<KeyCode>>,
+    mut events: EventReader<EditCommand>,
+    mut history: ResMut<EditHistory>,
+    mut simulation: ResMut<Simulation>,
+    map_query: Query<&Children, With<MapComponent>>,
+    mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
+) {
+    // collect the commands from the keyboard shortcuts and the controls panel
+    let mut commands: Vec<&EditCommand> = events.iter().collect();
+    if keyboard.any_pressed([KeyCode::LControl, KeyCode::RControl]) {
+        if keyboard.just_pressed(KeyCode::Z) {
+            commands.push(&EditCommand::Undo);
+        }
+        if keyboard.just_pressed(KeyCode::Y) {
+            commands.push(&EditCommand::Redo);
+        }
+    }
+    let mut changed = false;
+    for command in commands {
+        changed |= match command {
+            EditCommand::Undo => history.undo(&mut simulation),
+            EditCommand::Redo => history.redo(&mut simulation),
+        };
+    }
+    if changed {
+        sync_sprites(map_query.single(), &mut cell_query, &simulation.cells);
+    }
+}
+
+// keep the engine in line with the options chosen in the menu
+fn options_sync_system(mut simulation: ResMut<Simulation>, options: Res<GameOptions>) {
+    if options.is_changed() {
+        simulation.set_options(options.0.clone());
+    }
+}
+
+// record the generation shown unless the timeline already holds it as it is, so edits made
+// since the last generation are kept, branching if they were made in the past, without
+// copying every generation twice
+fn record_edits(timeline: &mut Timeline, simulation: &Simulation) {
+    let recorded = timeline
+        .get(timeline.position())
+        .is_some_and(|snapshot| simulation.matches(snapshot));
+    if !recorded {
+        timeline.record(simulation.snapshot());
+    }
+}
+
+fn fast_forward_system(
+    mut events: EventReader<FastForward>,
+    mut simulation: ResMut<Simulation>,
+    mut timeline: ResMut<Timeline>,
+    mut history: ResMut<EditHistory>,
+    mut error: ResMut<FastForwardError>,
+    mut stepping: ResMut<SteppedGenerations>,
+    map_query: Query<&Children, With<MapComponent>>,
+    mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
+) {
+    for FastForward(generations) in events.iter() {
+        record_edits(&mut timeline, &simulation);
+        error.0 = None;
+        if simulation.hashlife_rule().is_none() {
+            stepping.0 += generations;
+            continue;
+        }
+        if let Err(e) = simulation.fast_forward(*generations) {
+            error.0 = Some(e.to_string());
+            continue;
+        }
+        history.retain_unchanged(&simulation);
+        timeline.record(simulation.snapshot());
+        sync_sprites(map_query.single(), &mut cell_query, &simulation.cells);
+    }
+    if stepping.0 > 0 {
+        let per_frame = STEPPED_CELLS_PER_FRAME / simulation.stepped_cells().max(1);
+        let generations = (per_frame as u64).clamp(1, stepping.0);
+        for _ in 0..generations {
+            simulation.step();
+        }
+        stepping.0 -= generations;
+        history.retain_unchanged(&simulation);
+        // the skipped generations are kept as one, like a hashlife jump
+        if stepping.0 == 0 {
+            timeline.record(simulation.snapshot());
+        }
+        sync_sprites(map_query.single(), &mut cell_query, &simulation.cells);
+    }
+}
+
+fn timeline_panel_system(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut timeline: ResMut<Timeline>,
+    mut simulation: ResMut<Simulation>,
+    mut history: ResMut<EditHistory>,
+    state: Res<State<GameState>>,
+    map_query: Query<&Children, With<MapComponent>>,
+    mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
+) {
+    // nothing to go back to until the game has been played
+    if timeline.is_empty() {
+        return;
+    }
+    let mut position = timeline.position();
+    egui::TopBottomPanel::bottom("Timeline").show(egui_ctx.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            let generation = timeline.get(position).map_or(0, |s| s.generation);
+            // only move through the timeline while paused, so the game can't play on under the slider
+            ui.add_enabled(
+                state.current() == &GameState::Paused,
+                egui::Slider::new(&mut position, 0..=timeline.len() - 1)
+                    .show_value(false)
+                    .text(format!("Timeline: generation {}", generation)),
+            );
+            ui.label("Change a cell in a past generation to branch off from it.");
+        });
+    });
+    // go back or forward to the chosen generation
+    if position != timeline.position() {
+        if let Some(snapshot) = timeline.seek(position) {
+            simulation.restore(snapshot);
+            history.clear();
+            sync_sprites(map_query.single(), &mut cell_query, &simulation.cells);
+        }
+    }
+}
+
 fn generation_system(
-    mut cell_states: ResMut<CellStates>,
+    mut simulation: ResMut<Simulation>,
     map_query: Query<&mut Children, With<MapComponent>>,
     mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
     time: Res<Time>,
     mut game_time: ResMut<GameTimer>,
-    options: Res<GameOptions>,
     position: Res<CursorPosition>,
     mut history: ResMut<History>,
+    mut remaining: ResMut<RemainingGenerations>,
+    mut state: ResMut<State<GameState>>,
+    mut timeline: ResMut<Timeline>,
+    mut edits: ResMut<EditHistory>,
+    mut detector: ResMut<EndDetector>,
 ) {
-    // iterate over the cell data structure
-    for i in 0..cell_states.0.len() {
-        for j in 0..cell_states.0[i].len() {
-            let mut live_neighbors = 0;
-            let mut infected_neighbors = 0;
-            // count the neighbours
-            if i > 0 {
-                match cell_states.0[i - 1][j] {
-                    CellState::Alive => live_neighbors += 1,
-                    CellState::Infected => infected_neighbors += 1,
-                    _ => (),
-                }
-            }
-            if i > 0 && j > 0 {
-                match cell_states.0[i - 1][j - 1] {
-                    CellState::Alive => live_neighbors += 1,
-                    CellState::Infected => infected_neighbors += 1,
-                    _ => (),
-                }
-            }
-            if j > 0 {
-                match cell_states.0[i][j - 1] {
-                    CellState::Alive => live_neighbors += 1,
-                    CellState::Infected => infected_neighbors += 1,
-                    _ => (),
-                }
-            }
-            if i < MAP_SIZE.0 - 1 && j > 0 {
-                match cell_states.0[i + 1][j - 1] {
-                    CellState::Alive => live_neighbors += 1,
-                    CellState::Infected => infected_neighbors += 1,
-                    _ => (),
-                }
-            }
-            if i < MAP_SIZE.0 - 1 {
-                match cell_states.0[i + 1][j] {
-                    CellState::Alive => live_neighbors += 1,
-                    CellState::Infected => infected_neighbors += 1,
-                    _ => (),
-                }
+    // advance the engine only after each game tick
+    let ticked = game_time.0.tick(time.delta()).just_finished();
+    if ticked {
+        record_edits(&mut timeline, &simulation);
+        detector.observe(&simulation);
+        simulation.step();
+        timeline.record(simulation.snapshot());
+        detector.observe(&simulation);
+        // edits to cells the generation changed can't be undone, the timeline goes back further
+        edits.retain_unchanged(&simulation);
+        let ended = detector
+            .ending(&simulation, simulation.options.max_period)
+            .is_some();
+        if ended && simulation.options.auto_complete {
+            // move on to the results once nothing more can happen
+            remaining.0 = None;
+            state
+                .overwrite_set(GameState::Complete)
+                .expect("Failed to change state to Complete");
+        } else if let Some(generations) = remaining.0 {
+            // pause again once a set number of generations has been played
+            if generations > 1 {
+                remaining.0 = Some(generations - 1);
+            } else {
+                remaining.0 = None;
+                state
+                    .overwrite_set(GameState::Paused)
+                    .expect("Failed to change state to Paused");
             }
-            if i < MAP_SIZE.0 - 1 && j < MAP_SIZE.1 - 1 {
-                match cell_states.0[i + 1][j + 1] {
-                    CellState::Alive => live_neighbors += 1,
-                    CellState::Infected => infected_neighbors += 1,
-                    _ => (),
-                }
-            }
-            if j < MAP_SIZE.1 - 1 {
-                match cell_states.0[i][j + 1] {
-                    CellState::Alive => live_neighbors += 1,
-                    CellState::Infected => infected_neighbors += 1,
-                    _ => (),
-                }
-            }
-            if i > 0 && j < MAP_SIZE.1 - 1 {
-                match cell_states.0[i - 1][j + 1] {
-                    CellState::Alive => live_neighbors += 1,
-                    CellState::Infected => infected_neighbors += 1,
-                    _ => (),
-                }
-            }
-            cell_states.0[i][j] = cell_check(
-                cell_states.0[i][j],
-                live_neighbors,
-                infected_neighbors,
-                &options.0,
-            );
         }
     }
 
@@ -159,153 +313,25 @@ fn generation_system(
         let (mut cell, mut sprite) = cell_query.get_mut(child).unwrap();
         // set the cell selected by the cursor to its original colour
         if cell.coord == (position.0, position.1) {
-            update_sprite(cell.state, &mut sprite);
+            update_sprite(&cell, &mut sprite);
         }
-        // run only after each game tick
-        if game_time.0.tick(time.delta()).just_finished() {
+        // copy the new generation across only after each game tick
+        if ticked {
             // check if the cell entity is in a different state to its equivalent in the array
-            if cell.state != cell_states.0[cell.coord.0][cell.coord.1] {
+            let state = simulation.cells.get(cell.coord.0, cell.coord.1);
+            let strain = simulation.cells.strain(cell.coord.0, cell.coord.1);
+            if cell.state != state || cell.strain != strain {
                 // only record history if the program is compiled in debug mode
                 if cfg!(debug_assertions) {
                     history.0.push(format!(
                         "({}, {}): {:?} -> {:?}",
-                        cell.coord.0,
-                        cell.coord.1,
-                        cell.state,
-                        cell_states.0[cell.coord.0][cell.coord.1]
+                        cell.coord.0, cell.coord.1, cell.state, state
                     ));
                 }
-                cell.state = cell_states.0[cell.coord.0][cell.coord.1];
+                cell.state = state;
+                cell.strain = strain;
                 // update the sprite to reflect its state
-                update_sprite(cell.state, &mut sprite);
-            }
-        }
-    }
-}
-
-fn cell_check(
-    current_state: CellState,
-    live_neighbors: u8,
-    infected_neighbors: u8,
-    options: &Options,
-) -> CellState {
-    // check if the default options are in use
-    if options == &Options::default() {
-        // check the cell against the criteria
-        match current_state {
-            CellState::Alive => {
-                if live_neighbors < 2 {
-                    CellState::Dead
-                } else if live_neighbors == 2 || live_neighbors == 3 {
-                    CellState::Alive
-                } else if live_neighbors > 3 || infected_neighbors >= 2 {
-                    CellState::Infected
-                } else {
-                    CellState::Alive
-                }
-            }
-            CellState::Dead => {
-                if live_neighbors == 3 {
-                    CellState::Alive
-                } else {
-                    CellState::Dead
-                }
-            }
-            CellState::Infected => {
-                if live_neighbors < 3 {
-                    CellState::Dead
-                } else {
-                    CellState::Infected
-                }
-            }
-        }
-    } else {
-        // check the cell against the user defined criteria
-        match current_state {
-            CellState::Alive => match options.living_rule {
-                Rules::Single(i) => {
-                    if live_neighbors < i {
-                        CellState::Dead
-                    } else if live_neighbors == i {
-                        CellState::Alive
-                    } else if live_neighbors > i + 1
-                        || infected_neighbors >= (8 - options.virulence)
-                    {
-                        CellState::Infected
-                    } else {
-                        CellState::Alive
-                    }
-                }
-                Rules::Range { min: _, max: u } => {
-                    if !options.living_rule.in_range(&live_neighbors) {
-                        CellState::Dead
-                    } else if live_neighbors > u + 1
-                        || infected_neighbors >= (8 - options.virulence)
-                    {
-                        CellState::Infected
-                    } else {
-                        CellState::Alive
-                    }
-                }
-                Rules::Singles(_) => {
-                    if !options.living_rule.in_range(&live_neighbors) {
-                        CellState::Dead
-                    } else if live_neighbors > options.living_rule.max()
-                        || infected_neighbors >= (8 - options.virulence)
-                    {
-                        CellState::Infected
-                    } else {
-                        CellState::Alive
-                    }
-                }
-                _ => {
-                    if live_neighbors < 2 || (options.virulence == 0 && live_neighbors > 3) {
-                        CellState::Dead
-                    } else if live_neighbors == 2 || live_neighbors == 3 {
-                        CellState::Alive
-                    } else if live_neighbors > 3 || infected_neighbors >= 2 {
-                        CellState::Infected
-                    } else {
-                        CellState::Alive
-                    }
-                }
-            },
-            CellState::Dead => match options.dead_rule {
-                Rules::Single(i) => {
-                    if live_neighbors == i {
-                        CellState::Alive
-                    } else {
-                        CellState::Dead
-                    }
-                }
-                Rules::Range { min: _, max: _ } => {
-                    if options.dead_rule.in_range(&live_neighbors) {
-                        CellState::Alive
-                    } else {
-                        CellState::Dead
-                    }
-                }
-                Rules::Singles(_) => {
-                    if options.dead_rule.in_range(&live_neighbors) {
-                        CellState::Alive
-                    } else {
-                        CellState::Dead
-                    }
-                }
-                _ => {
-                    if live_neighbors == 3 {
-                        CellState::Alive
-                    } else {
-                        CellState::Dead
-                    }
-                }
-            },
-            CellState::Infected => {
-                if live_neighbors < options.virulence + 1 {
-                    CellState::Dead
-                } else {
-                    CellState::Infected
-                }
+                update_sprite(&cell, &mut sprite);
             }
         }
     }
@@ -1,9 +1,16 @@
 use bevy::prelude::*;
 
+use engine::{
+    cells::{CellState, CellStates},
+    edits::EditHistory,
+    options::GridLayout,
+    simulation::Simulation,
+};
+
 use crate::{
     ascii::spawn_sprite,
-    components::{CellComponent, CellState, MapComponent},
-    resources::{AsciiSheet, CameraPosition, CellStates, CursorPosition, PrevCursorPosition},
+    components::{CellComponent, MapComponent},
+    resources::{AsciiSheet, CameraPosition, CursorPosition, GameOptions, PrevCursorPosition},
     GameState, TILE_SIZE,
 };
 
@@ -14,30 +21,67 @@ impl Plugin for GridPlugin {
         app.insert_resource(CameraPosition::default())
             .insert_resource(CursorPosition::default())
             .insert_resource(PrevCursorPosition(0, 0))
+            .add_event::<ResizeGrid>()
             .add_startup_system(create_grid_system)
             .add_startup_system_to_stage(StartupStage::PostStartup, add_grid_system)
             .add_system_set(
                 SystemSet::on_update(GameState::Paused).with_system(cursor_movement_system),
             )
             .add_system(camera_movement_system)
-            .add_system(camera_update_system);
+            .add_system(camera_update_system)
+            .add_system(resize_grid_system);
+    }
+}
+
+// the largest grid that can be chosen from the options window
+pub const MAX_MAP_SIZE: usize = 1000;
+
+// event sent to change the size of the grid while the game is running
+pub struct ResizeGrid(pub usize, pub usize);
+
+// read a grid size written as WIDTHxHEIGHT e.g. 20x20, no bigger than the options window allows
+pub fn parse_map_size(s: &str) -> Option<(usize, usize)> {
+    let (width, height) = s.split_once('x')?;
+    let size = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    if !(1..=MAX_MAP_SIZE).contains(&size.0) || !(1..=MAX_MAP_SIZE).contains(&size.1) {
+        return None;
     }
+    Some(size)
 }
 
-pub const MAP_SIZE: (usize, usize) = (100, 100);
+fn create_grid_system(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    simulation: Res<Simulation>,
+    options: Res<GameOptions>,
+) {
+    spawn_grid(&mut commands, &ascii, &simulation.cells, options.0.layout);
+}
 
-fn create_grid_system(mut commands: Commands, ascii: Res<AsciiSheet>) {
+pub fn spawn_grid(
+    commands: &mut Commands,
+    ascii: &AsciiSheet,
+    cell_states: &CellStates,
+    layout: GridLayout,
+) -> Entity {
     let mut cells = Vec::new();
     // iterate over the 2D grid
-    for x in 0..MAP_SIZE.0 {
-        for y in 0..MAP_SIZE.1 {
-            // make a sprite from the texture atlas
+    for x in 0..cell_states.width() {
+        for y in 0..cell_states.height() {
+            // make a sprite from the texture atlas matching the state of the cell
+            let (state, strain) = (cell_states.get(x, y), cell_states.strain(x, y));
+            let (index, color) = sprite_appearance(state, strain);
+            // hex grids shift every odd row half a cell to the right
+            let offset = match layout {
+                GridLayout::Hex if y % 2 == 1 => TILE_SIZE / 2.,
+                _ => 0.,
+            };
             let cell = spawn_sprite(
-                &mut commands,
-                &ascii,
-                0,
-                Color::rgb(0., 0., 0.),
-                Vec3::new(x as f32 * TILE_SIZE, -(y as f32) * TILE_SIZE, 100.),
+                commands,
+                ascii,
+                index,
+                color,
+                Vec3::new(x as f32 * TILE_SIZE + offset, -(y as f32) * TILE_SIZE, 100.),
             );
             // define the cell name based on its coordinates
             let cell_name = format!("Cell ({},{})", &x, &y);
@@ -47,7 +91,8 @@ fn create_grid_system(mut commands: Commands, ascii: Res<AsciiSheet>) {
                 .insert(Name::new(cell_name))
                 .insert(CellComponent {
                     coord: (x, y),
-                    state: CellState::default(),
+                    state,
+                    strain,
                 });
             cells.push(cell);
         }
@@ -61,23 +106,30 @@ fn create_grid_system(mut commands: Commands, ascii: Res<AsciiSheet>) {
         .insert(GlobalTransform::default())
         .insert(ComputedVisibility::default())
         .insert(Visibility::visible())
-        .push_children(&cells);
+        .push_children(&cells)
+        .id()
 }
 
 fn add_grid_system(
     map_query: Query<&Children, With<MapComponent>>,
     cell_query: Query<(&CellComponent, &Transform)>,
-    mut cell_states: ResMut<CellStates>,
+    mut simulation: ResMut<Simulation>,
     mut camera_query: Query<&mut Transform, (Without<MapComponent>, Without<CellComponent>)>,
+    mut camera_position: ResMut<CameraPosition>,
+    mut cursor_position: ResMut<CursorPosition>,
 ) {
+    // start the camera and cursor in the centre of the grid
+    let (width, height) = simulation.cells.size();
+    (camera_position.0, camera_position.1) = (width / 2, height / 2);
+    (cursor_position.0, cursor_position.1) = (width / 2, height / 2);
     // add grid to array resource and centre camera
     let mut camera_transform = camera_query.single_mut(); // fetch the single camera entitity's transform component
     let children = map_query.single(); // fetch the single map entity's children
     for &child in children.iter() {
         // iterate over the children to access each individual entity
         let (cell, cell_transform) = cell_query.get(child).unwrap(); // get the cell component and the transform from the entity
-        cell_states.0[cell.coord.0][cell.coord.1] = cell.state; // add the corresponding cell component to the global array resource
-        if cell.coord.0 == MAP_SIZE.0 / 2 && cell.coord.1 == MAP_SIZE.1 / 2 {
+        simulation.cells.set(cell.coord.0, cell.coord.1, cell.state); // add the corresponding cell component to the global array resource
+        if cell.coord.0 == width / 2 && cell.coord.1 == height / 2 {
             // if the cell is in the centre
             // move the camera to the position of the cell
             (
@@ -88,59 +140,158 @@ fn add_grid_system(
     }
 }
 
+fn resize_grid_system(
+    mut commands: Commands,
+    mut events: EventReader<ResizeGrid>,
+    ascii: Res<AsciiSheet>,
+    map_query: Query<Entity, With<MapComponent>>,
+    mut simulation: ResMut<Simulation>,
+    options: Res<GameOptions>,
+    mut camera_position: ResMut<CameraPosition>,
+    mut cursor_position: ResMut<CursorPosition>,
+    mut prev_position: ResMut<PrevCursorPosition>,
+) {
+    // only the most recent size matters if several were sent this frame
+    if let Some(ResizeGrid(width, height)) = events.iter().last() {
+        // keep the existing pattern where it fits
+        simulation.resize(*width, *height);
+        // replace the old cell entities with ones matching the new grid
+        commands.entity(map_query.single()).despawn_recursive();
+        spawn_grid(&mut commands, &ascii, &simulation.cells, options.0.layout);
+        // keep the cursor and camera on the grid
+        (cursor_position.0, cursor_position.1) = (
+            cursor_position.0.min(width - 1),
+            cursor_position.1.min(height - 1),
+        );
+        (prev_position.0, prev_position.1) = (cursor_position.0, cursor_position.1);
+        (camera_position.0, camera_position.1) = (
+            camera_position.0.min(width - 1),
+            camera_position.1.min(height - 1),
+        );
+    }
+}
+
 fn cursor_movement_system(
     keyboard: Res<Input<KeyCode>>,
     mut position: ResMut<CursorPosition>,
     mut prev_position: ResMut<PrevCursorPosition>,
+    mut camera_position: ResMut<CameraPosition>,
+    mut simulation: ResMut<Simulation>,
+    options: Res<GameOptions>,
+    map_query: Query<&Children, With<MapComponent>>,
+    mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
 ) {
-    // update the cursor's position, and save the previous position
-    if keyboard.just_released(KeyCode::A) {
-        if position.0 > 0 {
-            (prev_position.0, prev_position.1) = (position.0, position.1);
-            position.0 -= 1;
-        }
+    // leave the keys alone while they're used for shortcuts, [CTRL]+[Z] would move a hex cursor
+    if keyboard.any_pressed([KeyCode::LControl, KeyCode::RControl]) {
+        return;
     }
-    if keyboard.just_released(KeyCode::D) {
-        if position.0 < MAP_SIZE.0 - 1 {
-            (prev_position.0, prev_position.1) = (position.0, position.1);
-            position.0 += 1;
+    let (width, height) = simulation.cells.size();
+    // the moves the cursor can make from its current cell
+    let moves = match options.0.layout {
+        GridLayout::Square => vec![
+            (KeyCode::A, (-1, 0)),
+            (KeyCode::D, (1, 0)),
+            (KeyCode::W, (0, -1)),
+            (KeyCode::S, (0, 1)),
+        ],
+        GridLayout::Hex => {
+            // odd rows sit half a cell to the right, so the diagonals depend on the row
+            let (west, east) = if position.1 % 2 == 1 { (0, 1) } else { (-1, 0) };
+            vec![
+                (KeyCode::A, (-1, 0)),
+                (KeyCode::D, (1, 0)),
+                (KeyCode::Q, (west, -1)),
+                (KeyCode::W, (east, -1)),
+                (KeyCode::Z, (west, 1)),
+                (KeyCode::X, (east, 1)),
+            ]
         }
-    }
-    if keyboard.just_released(KeyCode::W) {
-        if position.1 > 0 {
-            (prev_position.0, prev_position.1) = (position.0, position.1);
-            position.1 -= 1;
-        }
-    }
-    if keyboard.just_released(KeyCode::S) {
-        if position.1 < MAP_SIZE.1 - 1 {
-            (prev_position.0, prev_position.1) = (position.0, position.1);
-            position.1 += 1;
+    };
+    // update the cursor's position, and save the previous position
+    for (key, (dx, dy)) in moves {
+        if keyboard.just_released(key) {
+            let (mut x, mut y) = (position.0 as isize + dx, position.1 as isize + dy);
+            // an unbounded grid moves along under the cursor instead of stopping it at the edge
+            let (sx, sy) = simulation.scroll_to(x, y);
+            if (sx, sy) != (0, 0) {
+                (x, y) = (x - sx, y - sy);
+                (position.0, position.1) = (
+                    follow_scroll(position.0, sx, width),
+                    follow_scroll(position.1, sy, height),
+                );
+                (camera_position.0, camera_position.1) = (
+                    follow_scroll(camera_position.0, sx, width),
+                    follow_scroll(camera_position.1, sy, height),
+                );
+                sync_sprites(map_query.single(), &mut cell_query, &simulation.cells);
+            }
+            if x >= 0 && y >= 0 && x < width as isize && y < height as isize {
+                (prev_position.0, prev_position.1) = (position.0, position.1);
+                (position.0, position.1) = (x as usize, y as usize);
+            }
+            // only take one step a frame so the row the diagonals depend on stays correct
+            break;
         }
     }
 }
 
-fn camera_movement_system(keyboard: Res<Input<KeyCode>>, mut position: ResMut<CameraPosition>) {
+fn camera_movement_system(
+    keyboard: Res<Input<KeyCode>>,
+    mut position: ResMut<CameraPosition>,
+    mut cursor_position: ResMut<CursorPosition>,
+    mut prev_position: ResMut<PrevCursorPosition>,
+    mut simulation: ResMut<Simulation>,
+    map_query: Query<&Children, With<MapComponent>>,
+    mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
+) {
+    let (width, height) = simulation.cells.size();
+    let (mut x, mut y) = (position.0 as isize, position.1 as isize);
     // update the camera's position
     if keyboard.pressed(KeyCode::Left) {
-        if position.0 > 0 {
-            position.0 -= 1;
-        }
+        x -= 1;
     }
     if keyboard.pressed(KeyCode::Right) {
-        if position.0 < MAP_SIZE.0 - 1 {
-            position.0 += 1;
-        }
+        x += 1;
     }
     if keyboard.pressed(KeyCode::Up) {
-        if position.1 > 0 {
-            position.1 -= 1;
-        }
+        y -= 1;
     }
     if keyboard.pressed(KeyCode::Down) {
-        if position.1 < MAP_SIZE.1 - 1 {
-            position.1 += 1;
-        }
+        y += 1;
+    }
+    // an unbounded grid moves along under the camera instead of stopping it at the edge
+    let (sx, sy) = simulation.scroll_to(x, y);
+    if (sx, sy) != (0, 0) {
+        (x, y) = (x - sx, y - sy);
+        (cursor_position.0, cursor_position.1) = (
+            follow_scroll(cursor_position.0, sx, width),
+            follow_scroll(cursor_position.1, sy, height),
+        );
+        (prev_position.0, prev_position.1) = (cursor_position.0, cursor_position.1);
+        sync_sprites(map_query.single(), &mut cell_query, &simulation.cells);
+    }
+    (position.0, position.1) = (
+        x.clamp(0, width as isize - 1) as usize,
+        y.clamp(0, height as isize - 1) as usize,
+    );
+}
+
+// find where a position on the grid ends up after the grid scrolled, keeping it on the grid
+fn follow_scroll(position: usize, by: isize, size: usize) -> usize {
+    (position as isize - by).clamp(0, size as isize - 1) as usize
+}
+
+// bring every cell entity in line with the grid after it changed all at once
+pub fn sync_sprites(
+    children: &Children,
+    cell_query: &mut Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
+    cells: &CellStates,
+) {
+    for &child in children.iter() {
+        let (mut cell, mut sprite) = cell_query.get_mut(child).unwrap();
+        cell.state = cells.get(cell.coord.0, cell.coord.1);
+        cell.strain = cells.strain(cell.coord.0, cell.coord.1);
+        update_sprite(&cell, &mut sprite);
     }
 }
 
@@ -164,35 +315,39 @@ fn camera_update_system(
     }
 }
 
-pub fn update_sprite(state: CellState, sprite: &mut Mut<TextureAtlasSprite>) -> () {
+pub fn update_sprite(cell: &CellComponent, sprite: &mut Mut<TextureAtlasSprite>) -> () {
     // change sprite and colour based on the state
+    (sprite.index, sprite.color) = sprite_appearance(cell.state, cell.strain);
+}
+
+pub fn sprite_appearance(state: CellState, strain: u16) -> (usize, Color) {
+    // pick the sprite and colour that represent the state
     match state {
-        CellState::Alive => {
-            sprite.color = Color::rgb(255., 255., 255.);
-            sprite.index = 2;
-        }
-        CellState::Dead => {
-            sprite.color = Color::rgb(0., 0., 0.);
-            sprite.index = 0;
-        }
-        CellState::Infected => {
-            sprite.color = Color::rgb(0., 255., 0.);
-            sprite.index = 1;
-        }
+        CellState::Alive => (2, Color::rgb(255., 255., 255.)),
+        CellState::Dead => (0, Color::rgb(0., 0., 0.)),
+        CellState::Infected if strain == 0 => (1, Color::rgb(0., 255., 0.)),
+        // every later strain gets its own hue, a golden angle on from the one before
+        CellState::Infected => (1, Color::hsl((strain as f32 * 137.5) % 360., 1., 0.5)),
+        CellState::Recovered => (3, Color::rgb(0., 0., 255.)),
+        CellState::Exposed => (7, Color::rgb(255., 0., 255.)),
+        CellState::Vaccinated => (4, Color::rgb(0., 255., 255.)),
+        CellState::Barrier => (219, Color::rgb(0.5, 0.5, 0.5)),
     }
 }
 
 pub fn clear_grid(
     map_query: Query<&mut Children, With<MapComponent>>,
     mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
-    mut cell_states: ResMut<CellStates>,
+    mut simulation: ResMut<Simulation>,
+    mut history: ResMut<EditHistory>,
 ) -> () {
-    // set every entity to the default state and update in the array
+    // set every entity to the default state, then empty the simulation behind them
     let children = map_query.single();
     for &child in children.iter() {
         let (mut cell, mut sprite) = cell_query.get_mut(child).unwrap();
         cell.state = CellState::default();
-        update_sprite(cell.state, &mut sprite);
-        cell_states.0[cell.coord.0][cell.coord.1] = CellState::default();
+        cell.strain = 0;
+        update_sprite(&cell, &mut sprite);
     }
+    history.record(simulation.clear());
 }
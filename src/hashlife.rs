@@ -0,0 +1,512 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{cells::CellState, world::World};
+
+// the largest level the quadtree can grow to before coordinates stop fitting
+const MAX_LEVEL: u8 = 60;
+// the most nodes the memo holds before it's started over
+const MAX_NODES: usize = 1 << 20;
+
+// the pattern spread further than the quadtree can reach
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PatternTooLarge;
+
+impl fmt::Display for PatternTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The pattern is too large to fast-forward")
+    }
+}
+
+impl std::error::Error for PatternTooLarge {}
+
+// a life-like rule, whether a cell with a given number of living neighbours is born or survives
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LifeRule {
+    pub birth: [bool; 9],
+    pub survival: [bool; 9],
+}
+
+// a square of 2^level cells on a side, made of four squares half its size,
+// a level 0 node is a single cell
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    level: u8,
+    // the north west, north east, south west and south east quarters
+    children: [u32; 4],
+    population: u64,
+}
+
+// the dead and living single cells, the first two nodes of every quadtree
+const DEAD: u32 = 0;
+const ALIVE: u32 = 1;
+
+// a memoised quadtree that can skip a pattern forward by many generations at once,
+// identical squares are only stored once and only ever stepped once
+pub struct HashLife {
+    rule: LifeRule,
+    nodes: Vec<Node>,
+    // the node made from each set of four children
+    joined: HashMap<[u32; 4], u32>,
+    // the centre of a node after 2^n generations
+    results: HashMap<(u32, u8), u32>,
+    // the empty node of each level
+    empty: Vec<u32>,
+    // the whole pattern, centred on (0, 0)
+    root: u32,
+    // the most nodes the memo holds before it's started over
+    max_nodes: usize,
+}
+
+impl HashLife {
+    pub fn new(rule: LifeRule) -> Self {
+        let leaf = |population| Node {
+            level: 0,
+            children: [DEAD; 4],
+            population,
+        };
+        let mut life = Self {
+            rule,
+            nodes: vec![leaf(0), leaf(1)],
+            joined: HashMap::new(),
+            results: HashMap::new(),
+            empty: vec![DEAD],
+            root: DEAD,
+            max_nodes: MAX_NODES,
+        };
+        life.root = life.empty(3);
+        life
+    }
+
+    // copy the living cells of a world into a quadtree
+    pub fn from_world(world: &World, rule: LifeRule) -> Self {
+        let mut life = Self::new(rule);
+        for ((x, y), state) in world.cells() {
+            if state == CellState::Alive {
+                life.set(x, y);
+            }
+        }
+        life
+    }
+
+    // copy the living cells back out into a world
+    pub fn to_world(&self) -> World {
+        let mut world = World::default();
+        let half = self.half(self.root);
+        self.for_each_alive(self.root, -half, -half, &mut |x, y| {
+            world.set(x, y, CellState::Alive)
+        });
+        world
+    }
+
+    // the number of living cells, only the tests need it
+    #[cfg(test)]
+    pub fn population(&self) -> u64 {
+        self.nodes[self.root as usize].population
+    }
+
+    // bring a cell to life
+    pub fn set(&mut self, x: i64, y: i64) {
+        // grow the tree until the cell is inside it
+        while {
+            let half = self.half(self.root);
+            x < -half || y < -half || x >= half || y >= half
+        } {
+            self.expand();
+        }
+        let half = self.half(self.root);
+        self.root = self.set_in(self.root, x + half, y + half);
+    }
+
+    // advance the pattern by any number of generations, a power of two at a time
+    pub fn advance(&mut self, generations: u64) -> Result<(), PatternTooLarge> {
+        for n in 0..u64::BITS as u8 {
+            if generations >> n & 1 == 1 {
+                self.advance_by_power(n)?;
+                if self.nodes.len() > self.max_nodes {
+                    self.compact();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // advance the pattern by 2^n generations, starting the memo over when it fills up
+    // partway through, and taking the jump in halves if it doesn't fit in a fresh one
+    fn advance_by_power(&mut self, n: u8) -> Result<(), PatternTooLarge> {
+        for _ in 0..2 {
+            if self.try_advance_by_power(n)? {
+                return Ok(());
+            }
+            self.compact();
+        }
+        if n == 0 {
+            return Err(PatternTooLarge);
+        }
+        self.advance_by_power(n - 1)?;
+        self.advance_by_power(n - 1)
+    }
+
+    // advance the pattern by 2^n generations, returning whether the memo had room to
+    fn try_advance_by_power(&mut self, n: u8) -> Result<bool, PatternTooLarge> {
+        // nothing travels faster than a cell a generation, so with the pattern in the
+        // middle quarter of the tree it stays inside the centre that gets worked out
+        while {
+            let root = self.nodes[self.root as usize];
+            let centre = self.centre(self.root);
+            let middle = self.centre(centre);
+            root.level < n + 3 || self.nodes[middle as usize].population != root.population
+        } {
+            if self.nodes[self.root as usize].level >= MAX_LEVEL {
+                return Err(PatternTooLarge);
+            }
+            self.expand();
+        }
+        match self.next(self.root, n) {
+            Some(root) => {
+                self.root = root;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // start the memo over with only the squares of the current pattern,
+    // so a long jump doesn't hold on to every square it passed through
+    fn compact(&mut self) {
+        let mut alive = Vec::new();
+        let half = self.half(self.root);
+        self.for_each_alive(self.root, -half, -half, &mut |x, y| alive.push((x, y)));
+        *self = Self {
+            max_nodes: self.max_nodes,
+            ..Self::new(self.rule)
+        };
+        for (x, y) in alive {
+            self.set(x, y);
+        }
+    }
+
+    // the number of cells from the centre of a node to its edges
+    fn half(&self, node: u32) -> i64 {
+        1 << (self.nodes[node as usize].level - 1)
+    }
+
+    fn children(&self, node: u32) -> [u32; 4] {
+        self.nodes[node as usize].children
+    }
+
+    // find or make the node with these four quarters
+    fn join(&mut self, children: [u32; 4]) -> u32 {
+        if let Some(&node) = self.joined.get(&children) {
+            return node;
+        }
+        let node = Node {
+            level: self.nodes[children[0] as usize].level + 1,
+            children,
+            population: children
+                .iter()
+                .map(|&child| self.nodes[child as usize].population)
+                .sum(),
+        };
+        self.nodes.push(node);
+        let id = self.nodes.len() as u32 - 1;
+        self.joined.insert(children, id);
+        id
+    }
+
+    fn empty(&mut self, level: u8) -> u32 {
+        while self.empty.len() <= level as usize {
+            let below = self.empty[self.empty.len() - 1];
+            let node = self.join([below; 4]);
+            self.empty.push(node);
+        }
+        self.empty[level as usize]
+    }
+
+    // double the size of the tree, keeping the pattern in the middle
+    fn expand(&mut self) {
+        let [nw, ne, sw, se] = self.children(self.root);
+        let level = self.nodes[self.root as usize].level;
+        let e = self.empty(level - 1);
+        self.root = {
+            let nw = self.join([e, e, e, nw]);
+            let ne = self.join([e, e, ne, e]);
+            let sw = self.join([e, sw, e, e]);
+            let se = self.join([se, e, e, e]);
+            self.join([nw, ne, sw, se])
+        };
+    }
+
+    // the node half the size sitting in the middle of a node
+    fn centre(&mut self, node: u32) -> u32 {
+        let [nw, ne, sw, se] = self.children(node);
+        self.join([
+            self.children(nw)[3],
+            self.children(ne)[2],
+            self.children(sw)[1],
+            self.children(se)[0],
+        ])
+    }
+
+    // bring a cell to life in a node, counting from its top left corner
+    fn set_in(&mut self, node: u32, x: i64, y: i64) -> u32 {
+        let level = self.nodes[node as usize].level;
+        if level == 0 {
+            return ALIVE;
+        }
+        let half = 1 << (level - 1);
+        let quarter = (x >= half) as usize + 2 * (y >= half) as usize;
+        let mut children = self.children(node);
+        children[quarter] = self.set_in(children[quarter], x % half, y % half);
+        self.join(children)
+    }
+
+    fn get(&self, node: u32, x: i64, y: i64) -> bool {
+        let level = self.nodes[node as usize].level;
+        if level == 0 {
+            return node == ALIVE;
+        }
+        let half = 1 << (level - 1);
+        let quarter = (x >= half) as usize + 2 * (y >= half) as usize;
+        self.get(self.children(node)[quarter], x % half, y % half)
+    }
+
+    fn for_each_alive(&self, node: u32, x: i64, y: i64, f: &mut impl FnMut(i64, i64)) {
+        let Node {
+            level,
+            children,
+            population,
+        } = self.nodes[node as usize];
+        if population == 0 {
+            return;
+        }
+        if level == 0 {
+            f(x, y);
+            return;
+        }
+        let half = 1 << (level - 1);
+        for (quarter, &child) in children.iter().enumerate() {
+            let (dx, dy) = ((quarter % 2) as i64 * half, (quarter / 2) as i64 * half);
+            self.for_each_alive(child, x + dx, y + dy, f);
+        }
+    }
+
+    // the centre of a node of level k after 2^n generations, with n at most k - 2,
+    // none once the memo is full
+    fn next(&mut self, node: u32, n: u8) -> Option<u32> {
+        let level = self.nodes[node as usize].level;
+        if self.nodes[node as usize].population == 0 {
+            return Some(self.empty(level - 1));
+        }
+        if let Some(&result) = self.results.get(&(node, n)) {
+            return Some(result);
+        }
+        if self.nodes.len() > self.max_nodes {
+            return None;
+        }
+        let result = if level == 2 {
+            self.step_four_by_four(node)
+        } else {
+            let [a, b, c, d] = self.children(node);
+            let [a, b, c, d] = [a, b, c, d].map(|child| self.children(child));
+            // nine overlapping squares half the size of the node
+            let squares = [
+                self.join(a),
+                self.join([a[1], b[0], a[3], b[2]]),
+                self.join(b),
+                self.join([a[2], a[3], c[0], c[1]]),
+                self.join([a[3], b[2], c[1], d[0]]),
+                self.join([b[2], b[3], d[0], d[1]]),
+                self.join(c),
+                self.join([c[1], d[0], c[3], d[2]]),
+                self.join(d),
+            ];
+            // at full speed both halves of the jump are stepped, otherwise
+            // the first half just takes the centres
+            let full = n == level - 2;
+            let inner = if full { level - 3 } else { n };
+            let mut r = [DEAD; 9];
+            for (r, square) in r.iter_mut().zip(squares) {
+                *r = if full {
+                    self.next(square, inner)?
+                } else {
+                    self.centre(square)
+                };
+            }
+            let quarters = [
+                self.join([r[0], r[1], r[3], r[4]]),
+                self.join([r[1], r[2], r[4], r[5]]),
+                self.join([r[3], r[4], r[6], r[7]]),
+                self.join([r[4], r[5], r[7], r[8]]),
+            ];
+            let mut stepped = [DEAD; 4];
+            for (stepped, quarter) in stepped.iter_mut().zip(quarters) {
+                *stepped = self.next(quarter, inner)?;
+            }
+            self.join(stepped)
+        };
+        self.results.insert((node, n), result);
+        Some(result)
+    }
+
+    // the middle four cells of a four by four node after one generation
+    fn step_four_by_four(&mut self, node: u32) -> u32 {
+        let mut centre = [DEAD; 4];
+        for (i, cell) in centre.iter_mut().enumerate() {
+            let (x, y) = (1 + (i % 2) as i64, 1 + (i / 2) as i64);
+            let mut live_neighbors = 0;
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if (dx, dy) != (0, 0) && self.get(node, x + dx, y + dy) {
+                        live_neighbors += 1;
+                    }
+                }
+            }
+            let alive = if self.get(node, x, y) {
+                self.rule.survival[live_neighbors]
+            } else {
+                self.rule.birth[live_neighbors]
+            };
+            if alive {
+                *cell = ALIVE;
+            }
+        }
+        self.join(centre)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cells::CellStates,
+        fixtures::scatter,
+        options::{Boundary, Options, Rules},
+        simulation::Simulation,
+    };
+
+    // conway's life, B3/S23
+    const LIFE: LifeRule = LifeRule {
+        birth: [false, false, false, true, false, false, false, false, false],
+        survival: [false, false, true, true, false, false, false, false, false],
+    };
+
+    // a third of the cells alive, since the rule here only knows living and dead
+    fn scattered(width: usize, height: usize) -> CellStates {
+        let mut cells = CellStates::new(width, height);
+        let states = [CellState::Alive, CellState::Dead, CellState::Dead];
+        scatter(&mut cells, 0..width, 0..height, 7, &states);
+        cells
+    }
+
+    fn unbounded(cells: CellStates, options: Options) -> Simulation {
+        Simulation::new(
+            cells,
+            Options {
+                virulence: 0,
+                boundary: Boundary::Unbounded,
+                ..options
+            },
+        )
+    }
+
+    #[test]
+    fn fast_forward_matches_stepping() {
+        for options in [
+            Options::default(),
+            // highlife, B36/S23
+            Options {
+                dead_rule: Rules::Singles(vec![3, 6]),
+                ..Options::default()
+            },
+        ] {
+            for generations in [1, 2, 7, 64, 150] {
+                let mut stepped = unbounded(scattered(40, 30), options.clone());
+                let mut skipped = unbounded(scattered(40, 30), options.clone());
+                assert!(skipped.hashlife_rule().is_some());
+                for _ in 0..generations {
+                    stepped.step();
+                }
+                skipped.fast_forward(generations).unwrap();
+                assert_eq!(skipped.world, stepped.world);
+                assert_eq!(skipped.cells, stepped.cells);
+                assert_eq!(skipped.generation, generations);
+            }
+        }
+    }
+
+    #[test]
+    fn glider_travels_a_million_generations() {
+        let mut life = HashLife::new(LIFE);
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        for (x, y) in glider {
+            life.set(x, y);
+        }
+        life.advance(1 << 20).unwrap();
+        assert_eq!(life.population(), 5);
+        let world = life.to_world();
+        for (x, y) in glider {
+            assert_eq!(world.get(x + (1 << 18), y + (1 << 18)), CellState::Alive);
+        }
+    }
+
+    #[test]
+    fn starting_the_memo_over_keeps_the_pattern() {
+        let mut life = HashLife::from_world(&World::from_cells(&scattered(40, 30), (0, 0)), LIFE);
+        let mut compacted =
+            HashLife::from_world(&World::from_cells(&scattered(40, 30), (0, 0)), LIFE);
+        life.advance(100).unwrap();
+        compacted.advance(100).unwrap();
+        compacted.compact();
+        assert_eq!(compacted.to_world(), life.to_world());
+        life.advance(100).unwrap();
+        compacted.advance(100).unwrap();
+        assert_eq!(compacted.to_world(), life.to_world());
+    }
+
+    #[test]
+    fn patterns_too_far_out_are_refused() {
+        let mut life = HashLife::new(LIFE);
+        life.set(1 << 58, 0);
+        assert_eq!(life.advance(1), Err(PatternTooLarge));
+        // or too large for the memo to hold even a single generation
+        let mut life = HashLife::from_world(&World::from_cells(&scattered(40, 30), (0, 0)), LIFE);
+        life.max_nodes = 10;
+        assert_eq!(life.advance(1), Err(PatternTooLarge));
+    }
+
+    #[test]
+    fn long_jumps_stay_within_the_memo() {
+        let world = World::from_cells(&scattered(40, 30), (0, 0));
+        let mut life = HashLife::from_world(&world, LIFE);
+        let mut bounded = HashLife::from_world(&world, LIFE);
+        bounded.max_nodes = 20_000;
+        life.advance(1 << 12).unwrap();
+        bounded.advance(1 << 12).unwrap();
+        assert_eq!(bounded.to_world(), life.to_world());
+        // a jump only goes a few joins past the limit before it's started over
+        assert!(bounded.nodes.len() < 20_100);
+    }
+
+    #[test]
+    fn infected_cells_fall_back_to_stepping() {
+        let mut cells = scattered(20, 20);
+        cells.set(10, 10, CellState::Infected);
+        let mut stepped = unbounded(cells.clone(), Options::default());
+        let mut skipped = unbounded(cells, Options::default());
+        assert!(skipped.hashlife_rule().is_none());
+        for _ in 0..10 {
+            stepped.step();
+        }
+        skipped.fast_forward(10).unwrap();
+        assert_eq!(skipped.world, stepped.world);
+        // the default virulence can infect crowded cells, so it isn't plain life
+        let skipped = Simulation::new(
+            scattered(20, 20),
+            Options {
+                boundary: Boundary::Unbounded,
+                ..Options::default()
+            },
+        );
+        assert!(skipped.hashlife_rule().is_none());
+    }
+}
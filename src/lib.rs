@@ -0,0 +1,14 @@
+// the simulation behind the game, kept apart from bevy so it can be built and tested on its own
+pub mod cells;
+pub mod edits;
+pub mod ending;
+#[cfg(test)]
+mod fixtures;
+pub mod hashlife;
+pub mod options;
+pub mod rng;
+pub mod rulestring;
+pub mod simulation;
+pub mod strains;
+pub mod timeline;
+pub mod world;
@@ -15,17 +15,27 @@ mod resultmenu;
 use ascii::AsciiPlugin;
 use components::{CellComponent, MapComponent};
 use debug::DebugPlugin;
-use generation::GenerationPlugin;
-use grid::{GridPlugin, MAP_SIZE};
+use engine::{
+    cells::{CellStates, DEFAULT_MAP_SIZE},
+    edits::EditHistory,
+    options::{GridLayout, Options},
+    simulation::Simulation,
+};
+use generation::{
+    EditCommand, FastForward, GenerationPlugin, MAX_FAST_FORWARD, MAX_STEPPED_FAST_FORWARD,
+};
+use grid::{parse_map_size, GridPlugin, MAX_MAP_SIZE};
 use optionsmenu::OptionsMenuPlugin;
-use resources::{CameraPosition, CellStates, CursorPosition, PrevCursorPosition};
+use resources::{
+    CameraPosition, CursorPosition, FastForwardError, FastForwardGenerations, GameOptions,
+    PrevCursorPosition, RemainingGenerations, RunGenerations, SteppedGenerations,
+};
 use resultmenu::ResultMenuPlugin;
 
 // define global game constants
 pub const RESOLUTION: f32 = 16. / 9.;
 pub const SCREEN_HEIGHT: f32 = 900.;
 pub const TILE_SIZE: f32 = 20.;
-pub const BASE_TICK_SPEED: f32 = 0.5;
 
 // define window size
 pub struct WinSize {
@@ -52,6 +62,8 @@ pub enum GameState {
 }
 
 fn main() {
+    // read the size of the grid from the command line
+    let map_size = map_size_from_args();
     // create new app
     App::new()
         .add_state(GameState::Paused) // add the game state
@@ -62,7 +74,10 @@ fn main() {
             height: WinSize::default().h,
             ..Default::default()
         })
-        .init_resource::<CellStates>() // insert the cell state global resource
+        .insert_resource(Simulation::new(
+            CellStates::new(map_size.0, map_size.1),
+            Options::default(),
+        )) // insert the simulation global resource
         .add_plugins(DefaultPlugins) // add the plugins
         .add_plugin(AsciiPlugin)
         .add_plugin(EguiPlugin)
@@ -77,6 +92,24 @@ fn main() {
         .run();
 }
 
+// read a grid size given as --size WIDTHxHEIGHT e.g. --size 20x20
+fn map_size_from_args() -> (usize, usize) {
+    let args: Vec<String> = std::env::args().collect();
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--size" {
+            match args.get(i + 1).and_then(|s| parse_map_size(s)) {
+                Some(size) => return size,
+                None => eprintln!(
+                    "Invalid grid size, expected --size WIDTHxHEIGHT e.g. --size 20x20, \
+                     with each side at most {}",
+                    MAX_MAP_SIZE
+                ),
+            }
+        }
+    }
+    DEFAULT_MAP_SIZE
+}
+
 fn setup_system(mut commands: Commands, mut windows: ResMut<Windows>) {
     // camera
     commands.spawn_bundle(Camera2dBundle::default());
@@ -89,12 +122,21 @@ fn setup_system(mut commands: Commands, mut windows: ResMut<Windows>) {
     commands.insert_resource(win_size);
 }
 
-fn game_state_system(mut state: ResMut<State<GameState>>, keyboard: Res<Input<KeyCode>>) {
+fn game_state_system(
+    mut state: ResMut<State<GameState>>,
+    keyboard: Res<Input<KeyCode>>,
+    mut remaining: ResMut<RemainingGenerations>,
+    run: Res<RunGenerations>,
+) {
     if keyboard.just_released(KeyCode::E) {
         match state.current() {
-            GameState::Paused => state
-                .set(GameState::Play)
-                .expect("Failed to change state to Paused"),
+            GameState::Paused => {
+                // play until stopped rather than for a set number of generations
+                remaining.0 = None;
+                state
+                    .set(GameState::Play)
+                    .expect("Failed to change state to Paused")
+            }
             GameState::Play => state
                 .set(GameState::Paused)
                 .expect("Failed to change state to Play"),
@@ -111,6 +153,30 @@ fn game_state_system(mut state: ResMut<State<GameState>>, keyboard: Res<Input<Ke
                 .expect("Failed to change state to Complete"),
         }
     }
+    if keyboard.just_released(KeyCode::F) {
+        run_generations(&mut state, &mut remaining, 1);
+    }
+    if keyboard.just_released(KeyCode::R) {
+        run_generations(&mut state, &mut remaining, run.0);
+    }
+}
+
+// play a set number of generations, then pause again
+fn run_generations(
+    state: &mut State<GameState>,
+    remaining: &mut RemainingGenerations,
+    generations: u64,
+) {
+    match state.current() {
+        GameState::Paused => {
+            remaining.0 = Some(generations);
+            state
+                .set(GameState::Play)
+                .expect("Failed to change state to Play");
+        }
+        GameState::Play => remaining.0 = Some(generations),
+        _ => (),
+    }
 }
 
 fn controls_panel_system(
@@ -121,12 +187,41 @@ fn controls_panel_system(
     mut camera_position: ResMut<CameraPosition>,
     mut cursor_position: ResMut<CursorPosition>,
     mut prev_position: ResMut<PrevCursorPosition>,
+    simulation: Res<Simulation>,
+    options: Res<GameOptions>,
+    // grouped since a system can only take sixteen parameters
+    (mut fast_forward, fast_forward_error, stepping): (
+        ResMut<FastForwardGenerations>,
+        Res<FastForwardError>,
+        Res<SteppedGenerations>,
+    ),
+    mut fast_forward_events: EventWriter<FastForward>,
+    mut state: ResMut<State<GameState>>,
+    mut remaining: ResMut<RemainingGenerations>,
+    mut run: ResMut<RunGenerations>,
+    history: Res<EditHistory>,
+    mut edit_events: EventWriter<EditCommand>,
 ) {
+    let (width, height) = simulation.cells.size();
+    // long jumps are only quick when hashlife can be used
+    let max_fast_forward = if simulation.hashlife_rule().is_some() {
+        MAX_FAST_FORWARD
+    } else {
+        MAX_STEPPED_FAST_FORWARD
+    };
     egui::TopBottomPanel::bottom("Controls").show(egui_ctx.ctx_mut(), |ui| {
         ui.horizontal(|ui| {
-            ui.heading("Use [W][A][S][D] to move the cursor.");
+            ui.heading(format!("Generation: {}", simulation.generation));
+            match options.0.layout {
+                GridLayout::Square => ui.heading("Use [W][A][S][D] to move the cursor."),
+                GridLayout::Hex => ui.heading("Use [Q][W][A][D][Z][X] to move the cursor."),
+            };
             ui.heading("Press [SPACE] to change the cell.");
+            ui.heading("Press [V] to vaccinate living cells.");
+            ui.heading("Press [B] to build or remove a barrier.");
             ui.heading("Press [E] to to start and stop the game.");
+            ui.heading("Press [F] to step one generation, [R] to run several.");
+            ui.heading("Press [CTRL]+[Z] and [CTRL]+[Y] to undo and redo changes.");
             ui.heading("Press [ESC] to conclude the game.");
             ui.heading("Use arrow keys to pan the camera.");
             ui.with_layout(egui::Layout::right_to_left(egui::Align::RIGHT), |ui| {
@@ -135,7 +230,7 @@ fn controls_panel_system(
                     let children = map_query.single();
                     for &child in children.iter() {
                         let (cell, cell_transform) = cell_query.get(child).unwrap();
-                        if cell.coord.0 == MAP_SIZE.0 / 2 && cell.coord.1 == MAP_SIZE.1 / 2 {
+                        if cell.coord.0 == width / 2 && cell.coord.1 == height / 2 {
                             (
                                 camera_transform.translation.x,
                                 camera_transform.translation.y,
@@ -160,7 +255,56 @@ fn controls_panel_system(
                 }
                 if ui.button("Centre cursor").clicked() {
                     (prev_position.0, prev_position.1) = (cursor_position.0, cursor_position.1);
-                    (cursor_position.0, cursor_position.1) = (MAP_SIZE.0 / 2, MAP_SIZE.1 / 2);
+                    (cursor_position.0, cursor_position.1) = (width / 2, height / 2);
+                }
+                // edits can only be undone while paused
+                let paused = state.current() == &GameState::Paused;
+                let (undo, redo) = history.len();
+                if ui
+                    .add_enabled(
+                        paused && redo > 0,
+                        egui::Button::new(format!("Redo ({})", redo)),
+                    )
+                    .clicked()
+                {
+                    edit_events.send(EditCommand::Redo);
+                }
+                if ui
+                    .add_enabled(
+                        paused && undo > 0,
+                        egui::Button::new(format!("Undo ({})", undo)),
+                    )
+                    .clicked()
+                {
+                    edit_events.send(EditCommand::Undo);
+                }
+                if let Some(e) = &fast_forward_error.0 {
+                    ui.label(e.as_str());
+                }
+                if stepping.0 > 0 {
+                    ui.label(format!("{} generations to go", stepping.0));
+                }
+                if ui
+                    .add_enabled(stepping.0 == 0, egui::Button::new("Fast-forward"))
+                    .clicked()
+                {
+                    fast_forward_events.send(FastForward(fast_forward.0.min(max_fast_forward)));
+                }
+                ui.add(
+                    egui::DragValue::new(&mut fast_forward.0)
+                        .clamp_range(1..=max_fast_forward)
+                        .suffix(" generations"),
+                );
+                if ui.button("Run").clicked() {
+                    run_generations(&mut state, &mut remaining, run.0);
+                }
+                ui.add(
+                    egui::DragValue::new(&mut run.0)
+                        .clamp_range(1..=MAX_STEPPED_FAST_FORWARD)
+                        .suffix(" generations"),
+                );
+                if ui.button("Step").clicked() {
+                    run_generations(&mut state, &mut remaining, 1);
                 }
             });
         });
@@ -0,0 +1,446 @@
+use std::fmt;
+
+use crate::ending::DEFAULT_MAX_PERIOD;
+
+// the seconds between generations unless the options say otherwise
+pub const BASE_TICK_SPEED: f32 = 0.5;
+
+// define the alterable rules of the game
+#[derive(Default, Clone, Debug, Eq, PartialEq)]
+pub enum Rules {
+    Single(u8),
+    Range {
+        min: u8,
+        max: u8,
+    },
+    Singles(Vec<u8>),
+    #[default]
+    Default,
+}
+
+// the neighbour counts the default rules stand for
+pub const DEFAULT_LIVING_RULE: [u8; 2] = [2, 3];
+pub const DEFAULT_DEAD_RULE: [u8; 1] = [3];
+
+// define which cells a rule applies to, as the default rule differs between them
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RuleKind {
+    Living,
+    Dead,
+}
+
+impl RuleKind {
+    pub fn default_neighbours(self) -> &'static [u8] {
+        match self {
+            Self::Living => &DEFAULT_LIVING_RULE,
+            Self::Dead => &DEFAULT_DEAD_RULE,
+        }
+    }
+}
+
+// define the ways a rule can be invalid
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RuleError {
+    // the rule doesn't accept any number of neighbours
+    Empty,
+    // the rule accepts more neighbours than a cell has
+    TooManyNeighbours { value: u8, max: u8 },
+    // the lowest number of a range is above the highest
+    InvertedRange { min: u8, max: u8 },
+    // the virulence is above the number of neighbours a cell can have
+    TooVirulent { value: u8, max: u8 },
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "The birth rule needs at least one number of neighbours"),
+            Self::TooManyNeighbours { value, max } => write!(
+                f,
+                "{} neighbours is more than the {} a cell can have",
+                value, max
+            ),
+            Self::InvertedRange { min, max } => write!(
+                f,
+                "The lowest number of neighbours ({}) is above the highest ({})",
+                min, max
+            ),
+            Self::TooVirulent { value, max } => write!(
+                f,
+                "A virulence of {} is more than the {} neighbours a cell can have",
+                value, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+impl Rules {
+    // list the numbers of neighbours the rule accepts, in order
+    pub fn neighbours(&self, kind: RuleKind) -> Vec<u8> {
+        let mut neighbours = match self {
+            Self::Single(i) => vec![*i],
+            Self::Range { min: l, max: u } => (*l..=*u).collect(),
+            Self::Singles(v) => v.clone(),
+            Self::Default => kind.default_neighbours().to_vec(),
+        };
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        neighbours
+    }
+
+    // verify the criteria
+    pub fn in_range(&self, kind: RuleKind, num: u8) -> bool {
+        match self {
+            Self::Single(i) => num == *i,
+            Self::Range { min: l, max: u } => (*l..=*u).contains(&num),
+            Self::Singles(v) => v.contains(&num),
+            Self::Default => kind.default_neighbours().contains(&num),
+        }
+    }
+
+    // find the highest number of neighbours the rule accepts, if it accepts any
+    pub fn max(&self, kind: RuleKind) -> Option<u8> {
+        self.neighbours(kind).last().copied()
+    }
+
+    // check the rule can be met by a cell with up to max_neighbours neighbours, a survival
+    // rule may be left empty so nothing survives, as in Seeds (B2/S)
+    pub fn validate(&self, kind: RuleKind, max_neighbours: u8) -> Result<(), RuleError> {
+        if let Self::Range { min, max } = self {
+            if min > max {
+                return Err(RuleError::InvertedRange {
+                    min: *min,
+                    max: *max,
+                });
+            }
+        }
+        match self.neighbours(kind).last() {
+            None if kind == RuleKind::Living => Ok(()),
+            None => Err(RuleError::Empty),
+            Some(&value) if value > max_neighbours => Err(RuleError::TooManyNeighbours {
+                value,
+                max: max_neighbours,
+            }),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+// check the virulence doesn't ask for more neighbours than a cell with up to max_neighbours has
+pub fn validate_virulence(virulence: u8, max_neighbours: u8) -> Result<(), RuleError> {
+    if virulence > max_neighbours {
+        Err(RuleError::TooVirulent {
+            value: virulence,
+            max: max_neighbours,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+// define how the edges of the grid behave
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Boundary {
+    // everything beyond the edges is dead
+    #[default]
+    Dead,
+    // the edges wrap around onto the opposite side
+    Torus,
+    // the edges reflect the cells next to them
+    Mirror,
+    // the sides wrap around, the top and bottom wrap around flipped
+    KleinBottle,
+    // there are no edges, the grid shows the part of an endless plane around the cursor
+    Unbounded,
+}
+
+// define how the cells of the grid are arranged
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GridLayout {
+    // square cells in straight rows
+    #[default]
+    Square,
+    // hexagonal cells, with every odd row sitting half a cell to the right
+    Hex,
+}
+
+// the largest radius a neighbourhood can reach, keeping neighbour counts within a u8
+pub const MAX_NEIGHBOURHOOD_RADIUS: u8 = 7;
+
+// define which surrounding cells count as neighbours
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Neighbourhood {
+    // every cell within the radius, including diagonals
+    Moore(u8),
+    // every cell within the radius counting only horizontal and vertical steps
+    VonNeumann(u8),
+    // a hand picked set of offsets from the cell
+    Custom(Vec<(i8, i8)>),
+}
+
+impl Default for Neighbourhood {
+    fn default() -> Self {
+        Self::Moore(1)
+    }
+}
+
+impl Neighbourhood {
+    // list the offsets of every neighbour from the cell
+    pub fn offsets(&self) -> Vec<(isize, isize)> {
+        let mut offsets = Vec::new();
+        match self {
+            Self::Moore(r) | Self::VonNeumann(r) => {
+                let r = (*r).min(MAX_NEIGHBOURHOOD_RADIUS) as isize;
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        if let Self::VonNeumann(_) = self {
+                            if dx.abs() + dy.abs() > r {
+                                continue;
+                            }
+                        }
+                        offsets.push((dx, dy));
+                    }
+                }
+            }
+            Self::Custom(v) => {
+                let r = MAX_NEIGHBOURHOOD_RADIUS as i8;
+                for &(dx, dy) in v.iter() {
+                    if dx.abs() <= r
+                        && dy.abs() <= r
+                        && !offsets.contains(&(dx as isize, dy as isize))
+                    {
+                        offsets.push((dx as isize, dy as isize));
+                    }
+                }
+            }
+        }
+        // a cell is never its own neighbour
+        offsets.retain(|&offset| offset != (0, 0));
+        offsets
+    }
+
+    // the number of neighbours a cell has
+    pub fn size(&self) -> u8 {
+        match self {
+            Self::Moore(r) => {
+                let r = (*r).min(MAX_NEIGHBOURHOOD_RADIUS);
+                (2 * r + 1) * (2 * r + 1) - 1
+            }
+            Self::VonNeumann(r) => {
+                let r = (*r).min(MAX_NEIGHBOURHOOD_RADIUS);
+                2 * r * (r + 1)
+            }
+            Self::Custom(_) => self.offsets().len() as u8,
+        }
+    }
+}
+
+// define how infected cells pass the infection on to the living cells around them
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum Infection {
+    // a cell is infected once enough of its neighbours are, the virulence sets how many
+    #[default]
+    Threshold,
+    // each infected neighbour passes the infection on with the given probability every tick,
+    // the seed picks which rolls succeed so the same run can be played again
+    Chance {
+        probability: f32,
+        seed: u64,
+    },
+}
+
+// define how long recovered cells stay immune
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Immunity {
+    // recovered cells can never be infected again
+    #[default]
+    Permanent,
+    // recovered cells can be infected again after the given number of generations
+    Waning(u16),
+}
+
+// define how well vaccines work and how they're handed out
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vaccination {
+    // the chance a vaccinated cell resists the infection each time it would catch it
+    pub efficacy: f32,
+    // the share of living cells vaccinated each generation from the start of the campaign,
+    // there's no campaign when zero
+    pub rate: f32,
+    pub start: u64,
+    pub seed: u64,
+}
+
+impl Default for Vaccination {
+    fn default() -> Self {
+        Self {
+            efficacy: 0.9,
+            rate: 0.,
+            start: 0,
+            seed: 0,
+        }
+    }
+}
+
+// define how deadly the infection is and how it changes as it spreads
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Pathogen {
+    // the chance an infected cell dies of the first strain each generation
+    pub lethality: f32,
+    // the chance a cell that catches the infection gets a new strain of it,
+    // a little more or less virulent and deadly than the one it caught
+    pub mutation: f32,
+    pub seed: u64,
+}
+
+// define the game options
+#[derive(Clone, PartialEq)]
+pub struct Options {
+    pub living_rule: Rules,
+    pub dead_rule: Rules,
+    pub virulence: u8,
+    pub infection: Infection,
+    // the generations an exposed cell incubates the infection before becoming infected,
+    // cells that catch it are infected straight away when zero
+    pub incubation: u16,
+    // the generations an infected cell stays infected before recovering, never when zero
+    pub recovery: u16,
+    pub immunity: Immunity,
+    pub vaccination: Vaccination,
+    pub pathogen: Pathogen,
+    pub tick_speed: f32,
+    pub boundary: Boundary,
+    pub neighbourhood: Neighbourhood,
+    pub layout: GridLayout,
+    // move on to the results once the game ends by itself, looking for cycles up to max_period long
+    pub auto_complete: bool,
+    pub max_period: usize,
+}
+
+// define the default options
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            living_rule: Rules::default(),
+            dead_rule: Rules::default(),
+            virulence: 2,
+            infection: Infection::default(),
+            incubation: 0,
+            recovery: 0,
+            immunity: Immunity::default(),
+            vaccination: Vaccination::default(),
+            pathogen: Pathogen::default(),
+            tick_speed: BASE_TICK_SPEED,
+            boundary: Boundary::default(),
+            neighbourhood: Neighbourhood::default(),
+            layout: GridLayout::default(),
+            auto_complete: false,
+            max_period: DEFAULT_MAX_PERIOD,
+        }
+    }
+}
+
+impl Options {
+    // the number of neighbours a cell has, hex grids always use the six surrounding cells
+    pub fn max_neighbours(&self) -> u8 {
+        match self.layout {
+            GridLayout::Square => self.neighbourhood.size(),
+            GridLayout::Hex => 6,
+        }
+    }
+
+    // whether dead cells with no neighbours come alive, which would fill an endless plane at once
+    pub fn fills_the_plane(&self) -> bool {
+        self.dead_rule.in_range(RuleKind::Dead, 0)
+    }
+
+    // whether the next generation follows from the grid alone, with no rolls for catching
+    // the infection, campaigns, deaths or mutations, vaccines still roll to resist
+    pub fn deterministic(&self) -> bool {
+        self.infection == Infection::Threshold
+            && self.vaccination.rate <= 0.
+            && self.pathogen.lethality <= 0.
+            && self.pathogen.mutation <= 0.
+    }
+
+    // whether cells have to count how long they've been exposed, infected or recovered
+    pub fn timed(&self) -> bool {
+        self.incubation > 0 || self.recovery > 0 || matches!(self.immunity, Immunity::Waning(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbourhoods_have_the_expected_size() {
+        for neighbourhood in [
+            Neighbourhood::Moore(1),
+            Neighbourhood::Moore(2),
+            Neighbourhood::VonNeumann(1),
+            Neighbourhood::VonNeumann(3),
+            Neighbourhood::Custom(vec![(0, 0), (1, 0), (1, 0), (-2, 1)]),
+        ] {
+            assert_eq!(neighbourhood.size() as usize, neighbourhood.offsets().len());
+        }
+        assert_eq!(Neighbourhood::Moore(1).size(), 8);
+        assert_eq!(Neighbourhood::Moore(2).size(), 24);
+        assert_eq!(Neighbourhood::VonNeumann(1).size(), 4);
+        assert_eq!(Neighbourhood::VonNeumann(3).size(), 24);
+        assert_eq!(
+            Neighbourhood::Custom(vec![(0, 0), (1, 0), (1, 0), (-2, 1)]).size(),
+            2
+        );
+    }
+
+    #[test]
+    fn default_rules_resolve_to_their_neighbours() {
+        assert_eq!(Rules::Default.neighbours(RuleKind::Living), [2, 3]);
+        assert_eq!(Rules::Default.neighbours(RuleKind::Dead), [3]);
+        assert!(Rules::Default.in_range(RuleKind::Living, 2));
+        assert!(!Rules::Default.in_range(RuleKind::Dead, 2));
+        assert_eq!(Rules::Default.max(RuleKind::Living), Some(3));
+        assert_eq!(Rules::Singles(vec![]).max(RuleKind::Living), None);
+        assert_eq!(Rules::Singles(vec![5, 1]).max(RuleKind::Dead), Some(5));
+    }
+
+    #[test]
+    fn invalid_rules_are_reported() {
+        assert_eq!(Rules::Default.validate(RuleKind::Living, 8), Ok(()));
+        assert_eq!(Rules::Default.validate(RuleKind::Dead, 3), Ok(()));
+        assert_eq!(
+            Rules::Range { min: 2, max: 8 }.validate(RuleKind::Living, 8),
+            Ok(())
+        );
+        assert_eq!(
+            Rules::Singles(vec![]).validate(RuleKind::Dead, 8),
+            Err(RuleError::Empty)
+        );
+        // nothing has to survive, as in Seeds
+        assert_eq!(Rules::Singles(vec![]).validate(RuleKind::Living, 8), Ok(()));
+        assert_eq!(
+            Rules::Singles(vec![1, 9]).validate(RuleKind::Living, 8),
+            Err(RuleError::TooManyNeighbours { value: 9, max: 8 })
+        );
+        assert_eq!(
+            Rules::Single(5).validate(RuleKind::Dead, 4),
+            Err(RuleError::TooManyNeighbours { value: 5, max: 4 })
+        );
+        assert_eq!(
+            Rules::Range { min: 4, max: 2 }.validate(RuleKind::Living, 8),
+            Err(RuleError::InvertedRange { min: 4, max: 2 })
+        );
+        // the default virulence needs more neighbours than a one cell neighbourhood has
+        let options = Options {
+            neighbourhood: Neighbourhood::Custom(vec![(1, 0)]),
+            ..Options::default()
+        };
+        assert_eq!(
+            validate_virulence(options.virulence, options.max_neighbours()),
+            Err(RuleError::TooVirulent { value: 2, max: 1 })
+        );
+    }
+}
@@ -1,12 +1,24 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContext};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use engine::{
+    edits::EditHistory,
+    ending::MAX_PERIOD,
+    options::{
+        validate_virulence, Boundary, GridLayout, Immunity, Infection, Neighbourhood, Options,
+        RuleKind, Rules, MAX_NEIGHBOURHOOD_RADIUS,
+    },
+    rng::{Pattern, RandomFill},
+    rulestring::Rulestring,
+    simulation::Simulation,
+};
 
 use crate::{
     components::{CellComponent, MapComponent},
-    grid::clear_grid,
+    grid::{clear_grid, sync_sprites, ResizeGrid, MAX_MAP_SIZE},
     resources::{
-        CellStates, CurrentOptions, GameOptions, GameTimer, Options, Rules, SelectedRules,
+        CurrentOptions, GameOptions, GameTimer, NeighbourhoodShape, SelectedRules, VaccineBrush,
     },
     GameState,
 };
@@ -17,23 +29,169 @@ impl Plugin for OptionsMenuPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(GameOptions::default())
             .insert_resource(CurrentOptions::default())
+            .insert_resource(RandomFill::default())
+            .insert_resource(VaccineBrush::default())
+            .add_startup_system(map_size_value_system)
             .add_system_set(
                 SystemSet::on_update(GameState::Paused).with_system(options_menu_system),
             );
     }
 }
 
+fn map_size_value_system(mut current_rule: ResMut<CurrentOptions>, simulation: Res<Simulation>) {
+    // start the size sliders at the size the grid was launched with
+    current_rule.2.map_size_value = simulation.cells.size();
+}
+
 fn options_menu_system(
     mut egui_ctx: ResMut<EguiContext>,
     mut rule: ResMut<GameOptions>,
     mut current_rule: ResMut<CurrentOptions>,
     mut game_time: ResMut<GameTimer>,
     map_query: Query<&mut Children, With<MapComponent>>,
-    cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
-    cell_states: ResMut<CellStates>,
+    mut cell_query: Query<(&mut CellComponent, &mut TextureAtlasSprite)>,
+    mut simulation: ResMut<Simulation>,
+    mut history: ResMut<EditHistory>,
+    mut resize_events: EventWriter<ResizeGrid>,
+    mut random_fill: ResMut<RandomFill>,
+    mut brush: ResMut<VaccineBrush>,
 ) {
     // open new floating window
     egui::Window::new("Options").show(egui_ctx.ctx_mut(), |ui| {
+        // layout
+        ui.label("Grid Layout:");
+        ui.horizontal(|ui| {
+            let square = ui.radio_value(&mut rule.0.layout, GridLayout::Square, "Square");
+            let hex = ui.radio_value(&mut rule.0.layout, GridLayout::Hex, "Hexagonal");
+            // respawn the cells in their new positions
+            if square.changed() || hex.changed() {
+                resize_events.send(ResizeGrid(
+                    simulation.cells.width(),
+                    simulation.cells.height(),
+                ));
+            }
+        });
+
+        // neighbourhood
+        ui.label("Neighbourhood:");
+        if rule.0.layout == GridLayout::Hex {
+            ui.label("Hexagonal grids use the six surrounding cells.");
+        } else {
+            ui.horizontal(|ui| {
+                ui.radio_value(
+                    &mut current_rule.2.neighbourhood_shape,
+                    NeighbourhoodShape::Moore,
+                    "Moore",
+                );
+                ui.radio_value(
+                    &mut current_rule.2.neighbourhood_shape,
+                    NeighbourhoodShape::VonNeumann,
+                    "Von Neumann",
+                );
+                ui.radio_value(
+                    &mut current_rule.2.neighbourhood_shape,
+                    NeighbourhoodShape::Custom,
+                    "Custom",
+                );
+            });
+            match current_rule.2.neighbourhood_shape {
+                NeighbourhoodShape::Moore => {
+                    ui.add(
+                        egui::Slider::new(
+                            &mut current_rule.2.neighbourhood_radius,
+                            1..=MAX_NEIGHBOURHOOD_RADIUS,
+                        )
+                        .text("Radius"),
+                    );
+                    rule.0.neighbourhood =
+                        Neighbourhood::Moore(current_rule.2.neighbourhood_radius);
+                }
+                NeighbourhoodShape::VonNeumann => {
+                    ui.add(
+                        egui::Slider::new(
+                            &mut current_rule.2.neighbourhood_radius,
+                            1..=MAX_NEIGHBOURHOOD_RADIUS,
+                        )
+                        .text("Radius"),
+                    );
+                    rule.0.neighbourhood =
+                        Neighbourhood::VonNeumann(current_rule.2.neighbourhood_radius);
+                }
+                NeighbourhoodShape::Custom => {
+                    ui.label("Tick the cells around the centre that count as neighbours:");
+                    egui::Grid::new("Neighbourhood Mask").show(ui, |ui| {
+                        for (y, row) in current_rule.2.neighbourhood_mask.iter_mut().enumerate() {
+                            for (x, selected) in row.iter_mut().enumerate() {
+                                if (x, y) == (2, 2) {
+                                    ui.label("X");
+                                } else {
+                                    ui.checkbox(selected, "");
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    let mut offsets = Vec::new();
+                    for (y, row) in current_rule.2.neighbourhood_mask.iter().enumerate() {
+                        for (x, selected) in row.iter().enumerate() {
+                            if *selected && (x, y) != (2, 2) {
+                                offsets.push((x as i8 - 2, y as i8 - 2));
+                            }
+                        }
+                    }
+                    rule.0.neighbourhood = Neighbourhood::Custom(offsets);
+                }
+            }
+        }
+        // the rules can count up to every neighbour in the neighbourhood
+        let max_neighbours = rule.0.max_neighbours();
+
+        // rulestring
+        ui.label(format!("Rulestring: {}", Rulestring::from_options(&rule.0)));
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut current_rule.2.rulestring_value);
+            if ui.button("Apply").clicked() {
+                match current_rule.2.rulestring_value.parse::<Rulestring>() {
+                    // refuse rules the neighbourhood can't meet
+                    Ok(rulestring) => match (
+                        rulestring
+                            .survival
+                            .validate(RuleKind::Living, max_neighbours),
+                        rulestring.birth.validate(RuleKind::Dead, max_neighbours),
+                        rulestring
+                            .virulence
+                            .map_or(Ok(()), |v| validate_virulence(v, max_neighbours)),
+                    ) {
+                        (Err(e), _, _) => {
+                            current_rule.2.rulestring_error = Some(format!("Survival rule: {}", e))
+                        }
+                        (_, Err(e), _) => {
+                            current_rule.2.rulestring_error = Some(format!("Birth rule: {}", e))
+                        }
+                        (_, _, Err(e)) => {
+                            current_rule.2.rulestring_error = Some(format!("Infection: {}", e))
+                        }
+                        _ => {
+                            // show the new rules in the settings below so they stay in use
+                            select_rule(&mut current_rule.0, &rulestring.survival);
+                            select_rule(&mut current_rule.1, &rulestring.birth);
+                            if let Some(virulence) = rulestring.virulence {
+                                current_rule.2.virulence =
+                                    virulence == Options::default().virulence;
+                                current_rule.2.virulence_value = virulence;
+                            }
+                            rulestring.apply(&mut rule.0);
+                            current_rule.2.rulestring_error = None;
+                        }
+                    },
+                    Err(e) => current_rule.2.rulestring_error = Some(e.to_string()),
+                }
+            }
+        });
+        if let Some(e) = &current_rule.2.rulestring_error {
+            ui.label(e.as_str());
+        }
+
         // living cell
         ui.label("Living Cell Rule:");
         ui.checkbox(&mut current_rule.0.default, "Use default");
@@ -46,33 +204,49 @@ fn options_menu_system(
                 current_rule.0.singles = false;
                 current_rule.0.default = false;
                 ui.add(
-                    egui::Slider::new(&mut current_rule.0.single_value, 0..=8)
+                    egui::Slider::new(&mut current_rule.0.single_value, 0..=max_neighbours)
                         .text("Number of neighbours"),
                 );
-                rule.0.living_rule = Rules::Single(current_rule.0.single_value);
+                apply_rule(
+                    ui,
+                    &mut rule.0.living_rule,
+                    RuleKind::Living,
+                    Rules::Single(current_rule.0.single_value),
+                    max_neighbours,
+                );
             } else if current_rule.0.range {
                 current_rule.0.single = false;
                 current_rule.0.singles = false;
                 current_rule.0.default = false;
                 ui.add(
-                    egui::Slider::new(&mut current_rule.0.range_value.0, 0..=7)
-                        .text("Lowest number of neighbours"),
+                    egui::Slider::new(
+                        &mut current_rule.0.range_value.0,
+                        0..=max_neighbours.saturating_sub(1),
+                    )
+                    .text("Lowest number of neighbours"),
                 );
                 ui.add(
-                    egui::Slider::new(&mut current_rule.0.range_value.1, 1..=8)
+                    egui::Slider::new(&mut current_rule.0.range_value.1, 1..=max_neighbours)
                         .text("Highest number of neighbours"),
                 );
-                rule.0.living_rule = Rules::Range {
-                    min: current_rule.0.range_value.0,
-                    max: current_rule.0.range_value.1,
-                };
+                apply_rule(
+                    ui,
+                    &mut rule.0.living_rule,
+                    RuleKind::Living,
+                    Rules::Range {
+                        min: current_rule.0.range_value.0,
+                        max: current_rule.0.range_value.1,
+                    },
+                    max_neighbours,
+                );
             } else if current_rule.0.singles {
                 current_rule.0.single = false;
                 current_rule.0.range = false;
                 current_rule.0.default = false;
                 ui.label("List of possible neighbour values (separate by space e.g. 1 4 7 8)");
                 ui.text_edit_singleline(&mut current_rule.0.singles_value);
-                let string_out: Vec<&str> = current_rule.0.singles_value.split(" ").collect();
+                let string_out: Vec<&str> =
+                    current_rule.0.singles_value.split_whitespace().collect();
                 let mut singles = Vec::new();
                 for s in string_out {
                     match s.parse() {
@@ -82,11 +256,23 @@ fn options_menu_system(
                         }
                     }
                 }
-                rule.0.living_rule = Rules::Singles(singles);
+                apply_rule(
+                    ui,
+                    &mut rule.0.living_rule,
+                    RuleKind::Living,
+                    Rules::Singles(singles),
+                    max_neighbours,
+                );
             }
         } else {
             current_rule.0 = SelectedRules::default();
-            rule.0.living_rule = Rules::Default;
+            apply_rule(
+                ui,
+                &mut rule.0.living_rule,
+                RuleKind::Living,
+                Rules::Default,
+                max_neighbours,
+            );
         }
 
         // dead cell
@@ -101,33 +287,49 @@ fn options_menu_system(
                 current_rule.1.singles = false;
                 current_rule.1.default = false;
                 ui.add(
-                    egui::Slider::new(&mut current_rule.1.single_value, 0..=8)
+                    egui::Slider::new(&mut current_rule.1.single_value, 0..=max_neighbours)
                         .text("Number of neighbours"),
                 );
-                rule.0.dead_rule = Rules::Single(current_rule.1.single_value);
+                apply_rule(
+                    ui,
+                    &mut rule.0.dead_rule,
+                    RuleKind::Dead,
+                    Rules::Single(current_rule.1.single_value),
+                    max_neighbours,
+                );
             } else if current_rule.1.range {
                 current_rule.1.single = false;
                 current_rule.1.singles = false;
                 current_rule.1.default = false;
                 ui.add(
-                    egui::Slider::new(&mut current_rule.1.range_value.0, 0..=7)
-                        .text("Lowest number of neighbours"),
+                    egui::Slider::new(
+                        &mut current_rule.1.range_value.0,
+                        0..=max_neighbours.saturating_sub(1),
+                    )
+                    .text("Lowest number of neighbours"),
                 );
                 ui.add(
-                    egui::Slider::new(&mut current_rule.1.range_value.1, 1..=8)
+                    egui::Slider::new(&mut current_rule.1.range_value.1, 1..=max_neighbours)
                         .text("Highest number of neighbours"),
                 );
-                rule.0.dead_rule = Rules::Range {
-                    min: current_rule.1.range_value.0,
-                    max: current_rule.1.range_value.1,
-                };
+                apply_rule(
+                    ui,
+                    &mut rule.0.dead_rule,
+                    RuleKind::Dead,
+                    Rules::Range {
+                        min: current_rule.1.range_value.0,
+                        max: current_rule.1.range_value.1,
+                    },
+                    max_neighbours,
+                );
             } else if current_rule.1.singles {
                 current_rule.1.single = false;
                 current_rule.1.range = false;
                 current_rule.1.default = false;
                 ui.label("List of possible neighbour values (separate by space e.g. 1 4 7 8)");
                 ui.text_edit_singleline(&mut current_rule.1.singles_value);
-                let string_out: Vec<&str> = current_rule.1.singles_value.split(" ").collect();
+                let string_out: Vec<&str> =
+                    current_rule.1.singles_value.split_whitespace().collect();
                 let mut singles = Vec::new();
                 for s in string_out {
                     match s.parse() {
@@ -137,27 +339,133 @@ fn options_menu_system(
                         }
                     }
                 }
-                rule.0.dead_rule = Rules::Singles(singles);
+                apply_rule(
+                    ui,
+                    &mut rule.0.dead_rule,
+                    RuleKind::Dead,
+                    Rules::Singles(singles),
+                    max_neighbours,
+                );
             }
         } else {
             current_rule.1 = SelectedRules::default();
-            rule.0.dead_rule = Rules::Default;
+            apply_rule(
+                ui,
+                &mut rule.0.dead_rule,
+                RuleKind::Dead,
+                Rules::Default,
+                max_neighbours,
+            );
         }
 
         // infected cell
         ui.label("Virulence:");
         ui.checkbox(&mut current_rule.2.virulence, "Use default");
-        if !current_rule.2.virulence {
+        let virulence = if !current_rule.2.virulence {
             ui.add(
-                egui::Slider::new(&mut current_rule.2.virulence_value, 0..=8)
+                egui::Slider::new(&mut current_rule.2.virulence_value, 0..=max_neighbours)
                     .text("Higher = more infectious"),
             );
-            rule.0.virulence = current_rule.2.virulence_value;
+            current_rule.2.virulence_value
         } else {
             current_rule.2.virulence = true;
-            rule.0.virulence = Options::default().virulence;
+            Options::default().virulence
+        };
+        // like the rules, the virulence can't ask for more neighbours than a cell has
+        match validate_virulence(virulence, max_neighbours) {
+            Ok(()) => rule.0.virulence = virulence,
+            Err(e) => {
+                ui.label(format!("{}", e));
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut current_rule.2.infection_chance, false, "Threshold");
+            ui.radio_value(&mut current_rule.2.infection_chance, true, "Chance");
+        });
+        if current_rule.2.infection_chance {
+            ui.add(
+                egui::Slider::new(&mut current_rule.2.infection_probability, 0.0..=1.0)
+                    .text("Chance each infected neighbour passes it on"),
+            );
+            ui.horizontal(|ui| {
+                ui.label("Seed:");
+                ui.add(egui::DragValue::new(&mut current_rule.2.infection_seed));
+            });
+            rule.0.infection = Infection::Chance {
+                probability: current_rule.2.infection_probability,
+                seed: current_rule.2.infection_seed,
+            };
+        } else {
+            rule.0.infection = Infection::Threshold;
+        }
+
+        // exposed cell
+        ui.label("Incubation:");
+        ui.add(
+            egui::Slider::new(&mut rule.0.incubation, 0..=100)
+                .text("Generations exposed before becoming infectious, 0 = none"),
+        );
+
+        // recovered cell
+        ui.label("Recovery:");
+        ui.add(
+            egui::Slider::new(&mut rule.0.recovery, 0..=100)
+                .text("Generations infectious before recovering, 0 = never"),
+        );
+        ui.horizontal(|ui| {
+            ui.radio_value(
+                &mut current_rule.2.immunity_waning,
+                false,
+                "Permanent immunity",
+            );
+            ui.radio_value(&mut current_rule.2.immunity_waning, true, "Waning immunity");
+        });
+        if current_rule.2.immunity_waning {
+            ui.add(
+                egui::Slider::new(&mut current_rule.2.immunity_generations, 1..=100)
+                    .text("Generations immune"),
+            );
+            rule.0.immunity = Immunity::Waning(current_rule.2.immunity_generations);
+        } else {
+            rule.0.immunity = Immunity::Permanent;
         }
 
+        // vaccinated cell
+        ui.label("Vaccination:");
+        ui.add(
+            egui::Slider::new(&mut rule.0.vaccination.efficacy, 0.0..=1.0)
+                .text("Chance the vaccine holds off the infection"),
+        );
+        ui.add(
+            egui::Slider::new(&mut rule.0.vaccination.rate, 0.0..=1.0)
+                .text("Share of living cells vaccinated each generation, 0 = no campaign"),
+        );
+        ui.horizontal(|ui| {
+            ui.label("Campaign starts at generation:");
+            ui.add(egui::DragValue::new(&mut rule.0.vaccination.start));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Seed:");
+            ui.add(egui::DragValue::new(&mut rule.0.vaccination.seed));
+        });
+        ui.add(egui::Slider::new(&mut brush.0, 0..=10).text("Brush size, press [V] to vaccinate"));
+
+        // strains of the infection
+        ui.label("Pathogen:");
+        ui.add(
+            egui::Slider::new(&mut rule.0.pathogen.lethality, 0.0..=1.0)
+                .text("Chance an infected cell dies of the first strain each generation"),
+        );
+        ui.add(
+            egui::Slider::new(&mut rule.0.pathogen.mutation, 0.0..=1.0)
+                .text("Chance a caught infection mutates into a new strain, 0 = never"),
+        );
+        ui.horizontal(|ui| {
+            ui.label("Seed:");
+            ui.add(egui::DragValue::new(&mut rule.0.pathogen.seed));
+        });
+        ui.label(format!("Strains so far: {}", simulation.strains().count()));
+
         // tick speed
         ui.label("Tick Speed:");
         ui.checkbox(&mut current_rule.2.tick_speed, "Use default");
@@ -174,9 +482,138 @@ fn options_menu_system(
             game_time.0 = GameTimer::default().0;
         }
 
+        // ending
+        ui.label("Ending:");
+        ui.checkbox(
+            &mut rule.0.auto_complete,
+            "Conclude the game once it dies out, settles or repeats",
+        );
+        if !rule.0.deterministic() {
+            ui.label("Games left to chance only conclude once they die out.");
+        }
+        ui.add(
+            egui::Slider::new(&mut rule.0.max_period, 1..=MAX_PERIOD)
+                .logarithmic(true)
+                .text("Longest repeat to look for"),
+        );
+
+        // boundary
+        ui.label("Edges:");
+        ui.radio_value(&mut rule.0.boundary, Boundary::Dead, "Dead edge");
+        ui.radio_value(&mut rule.0.boundary, Boundary::Torus, "Wraparound (torus)");
+        ui.radio_value(&mut rule.0.boundary, Boundary::Mirror, "Mirror");
+        ui.radio_value(&mut rule.0.boundary, Boundary::KleinBottle, "Klein bottle");
+        let fills = rule.0.fills_the_plane();
+        ui.add_enabled_ui(!fills, |ui| {
+            ui.radio_value(&mut rule.0.boundary, Boundary::Unbounded, "Unbounded");
+        });
+        if fills {
+            if rule.0.boundary == Boundary::Unbounded {
+                rule.0.boundary = Boundary::Dead;
+            }
+            ui.label(
+                "Birth with no neighbours would fill an unbounded grid at once, \
+                 so it needs edges.",
+            );
+        }
+        if rule.0.layout == GridLayout::Hex && rule.0.boundary == Boundary::Mirror {
+            ui.label(
+                "Hexagonal rows only line up when reflected at the sides, \
+                 so the top and bottom stay dead.",
+            );
+        }
+
+        // grid size
+        ui.label(format!(
+            "Grid Size: {}x{}",
+            simulation.cells.width(),
+            simulation.cells.height()
+        ));
+        ui.add(
+            egui::Slider::new(&mut current_rule.2.map_size_value.0, 1..=MAX_MAP_SIZE)
+                .logarithmic(true)
+                .text("Width"),
+        );
+        ui.add(
+            egui::Slider::new(&mut current_rule.2.map_size_value.1, 1..=MAX_MAP_SIZE)
+                .logarithmic(true)
+                .text("Height"),
+        );
+        if ui.button("Resize grid").clicked() {
+            let (width, height) = current_rule.2.map_size_value;
+            resize_events.send(ResizeGrid(width, height));
+        }
+
+        // randomise
+        ui.label("Randomise:");
+        ui.add(egui::Slider::new(&mut random_fill.alive, 0.0..=1.0).text("Alive"));
+        ui.add(egui::Slider::new(&mut random_fill.infected, 0.0..=1.0).text("Infected"));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut random_fill.pattern, Pattern::Uniform, "Uniform");
+            ui.radio_value(&mut random_fill.pattern, Pattern::Clustered, "Clustered");
+            ui.radio_value(
+                &mut random_fill.pattern,
+                Pattern::PatientZero,
+                "Patient zero",
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Seed:");
+            ui.add(egui::DragValue::new(&mut random_fill.seed));
+            if ui.button("New seed").clicked() {
+                random_fill.seed = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_nanos() as u64);
+            }
+        });
+        if ui.button("Randomise grid").clicked() {
+            let (width, height) = simulation.cells.size();
+            let cells = random_fill.generate(width, height);
+            history.record(simulation.fill(&cells));
+            sync_sprites(map_query.single(), &mut cell_query, &simulation.cells);
+        }
+
         // clear grid
         if ui.button("Clear grid").clicked() {
-            clear_grid(map_query, cell_query, cell_states);
+            clear_grid(map_query, cell_query, simulation, history);
         }
     });
 }
+
+// show a rule in the checkboxes and sliders used to pick it
+fn select_rule(selected: &mut SelectedRules, rule: &Rules) {
+    *selected = SelectedRules::default();
+    match rule {
+        Rules::Single(i) => {
+            selected.single = true;
+            selected.single_value = *i;
+        }
+        Rules::Range { min, max } => {
+            selected.range = true;
+            selected.range_value = (*min, *max);
+        }
+        Rules::Singles(v) => {
+            selected.singles = true;
+            let singles: Vec<String> = v.iter().map(|i| i.to_string()).collect();
+            selected.singles_value = singles.join(" ");
+        }
+        Rules::Default => return,
+    }
+    selected.default = false;
+}
+
+// use a rule picked in the menu if it can be met, otherwise explain what is wrong with it
+fn apply_rule(
+    ui: &mut egui::Ui,
+    target: &mut Rules,
+    kind: RuleKind,
+    rule: Rules,
+    max_neighbours: u8,
+) {
+    match rule.validate(kind, max_neighbours) {
+        Ok(()) => *target = rule,
+        Err(e) => {
+            ui.label(format!("{}", e));
+        }
+    }
+}
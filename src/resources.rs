@@ -6,44 +6,26 @@ use bevy::{
 use bevy_inspector_egui::Inspectable;
 use std::time::Duration;
 
-use crate::{components::CellState, grid::MAP_SIZE, BASE_TICK_SPEED};
+use engine::{
+    cells::DEFAULT_MAP_SIZE,
+    options::{Options, BASE_TICK_SPEED},
+};
 
 #[derive(Component, Inspectable)]
 pub struct AsciiSheet(pub Handle<TextureAtlas>);
 
 // keep track of the cursors position on the grid
+#[derive(Default)]
 pub struct CursorPosition(pub usize, pub usize);
 
-impl Default for CursorPosition {
-    fn default() -> Self {
-        Self(MAP_SIZE.0 / 2, MAP_SIZE.1 / 2)
-    }
-}
-
 // keep track of the previous cursor location
 #[derive(Default)]
 pub struct PrevCursorPosition(pub usize, pub usize);
 
 // keep track of the camera position in relation to the grid
+#[derive(Default)]
 pub struct CameraPosition(pub usize, pub usize);
 
-// implement a starting position of the camera
-impl Default for CameraPosition {
-    fn default() -> Self {
-        Self(MAP_SIZE.0 / 2, MAP_SIZE.1 / 2)
-    }
-}
-
-// keep track of the state of cells in a data structure outside the game engine
-pub struct CellStates(pub [[CellState; MAP_SIZE.1]; MAP_SIZE.0]);
-
-// define the starting conditions of the game
-impl Default for CellStates {
-    fn default() -> Self {
-        Self([[CellState::default(); MAP_SIZE.1]; MAP_SIZE.0])
-    }
-}
-
 // keep track of the generation speed
 pub struct GameTimer(pub Timer);
 
@@ -53,90 +35,8 @@ impl Default for GameTimer {
     }
 }
 
-// define the alterable rules of the game
-#[derive(Default, Eq, PartialEq, Inspectable)]
-pub enum Rules {
-    Single(u8),
-    Range {
-        min: u8,
-        max: u8,
-    },
-    Singles(Vec<u8>),
-    #[default]
-    Default,
-}
-
-impl Rules {
-    // verify the criteria
-    pub fn in_range(&self, num: &u8) -> bool {
-        match self {
-            Self::Single(i) => {
-                if num == i {
-                    true
-                } else {
-                    false
-                }
-            }
-            Self::Range { min: l, max: u } => {
-                for i in *l..=*u {
-                    if num == &i {
-                        return true;
-                    }
-                }
-                false
-            }
-            Self::Singles(v) => {
-                for i in v.iter() {
-                    if num == i {
-                        return true;
-                    }
-                }
-                false
-            }
-            _ => panic!("Error, cannot check value in default rule"),
-        }
-    }
-    pub fn max(&self) -> u8 {
-        match self {
-            Self::Single(i) => *i,
-            Self::Range { min: _, max: u } => *u,
-            Self::Singles(v) => {
-                let mut max = 0;
-                for i in v.iter() {
-                    if &max < i {
-                        max = *i;
-                    }
-                }
-                max
-            }
-            _ => panic!("Error, cannot find maximum in default rule"),
-        }
-    }
-}
-
-// define the game options
-#[derive(PartialEq, Inspectable)]
-pub struct Options {
-    pub living_rule: Rules,
-    pub dead_rule: Rules,
-    pub virulence: u8,
-    pub tick_speed: f32,
-}
-
-// define the default options
-impl Default for Options {
-    fn default() -> Self {
-        Self {
-            living_rule: Rules::default(),
-            dead_rule: Rules::default(),
-            virulence: 2,
-            tick_speed: BASE_TICK_SPEED,
-        }
-    }
-}
-
 // define a wrapper for the options
-#[derive(Default, Inspectable)]
+#[derive(Default)]
 pub struct GameOptions(pub Options);
 
 // keep track of changing rules
@@ -164,12 +64,31 @@ impl Default for SelectedRules {
     }
 }
 
+// the neighbourhood shapes that can be picked in the options window
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum NeighbourhoodShape {
+    Moore,
+    VonNeumann,
+    Custom,
+}
+
 // keep track of changing settings
 pub struct SelectedOptions {
     pub virulence: bool,
     pub tick_speed: bool,
     pub virulence_value: u8,
+    pub infection_chance: bool,
+    pub infection_probability: f32,
+    pub infection_seed: u64,
+    pub immunity_waning: bool,
+    pub immunity_generations: u16,
     pub tick_speed_value: f32,
+    pub map_size_value: (usize, usize),
+    pub neighbourhood_shape: NeighbourhoodShape,
+    pub neighbourhood_radius: u8,
+    pub neighbourhood_mask: [[bool; 5]; 5],
+    pub rulestring_value: String,
+    pub rulestring_error: Option<String>,
 }
 
 impl Default for SelectedOptions {
@@ -178,7 +97,25 @@ impl Default for SelectedOptions {
             virulence: true,
             tick_speed: true,
             virulence_value: 2,
+            infection_chance: false,
+            infection_probability: 0.2,
+            infection_seed: 0,
+            immunity_waning: false,
+            immunity_generations: 10,
             tick_speed_value: BASE_TICK_SPEED,
+            map_size_value: DEFAULT_MAP_SIZE,
+            neighbourhood_shape: NeighbourhoodShape::Moore,
+            neighbourhood_radius: 1,
+            // start the custom mask off as the cells directly surrounding the centre
+            neighbourhood_mask: [
+                [false, false, false, false, false],
+                [false, true, true, true, false],
+                [false, true, false, true, false],
+                [false, true, true, true, false],
+                [false, false, false, false, false],
+            ],
+            rulestring_value: "B3/S23/I2".to_string(),
+            rulestring_error: None,
         }
     }
 }
@@ -187,6 +124,40 @@ impl Default for SelectedOptions {
 #[derive(Default)]
 pub struct CurrentOptions(pub SelectedRules, pub SelectedRules, pub SelectedOptions);
 
+// the number of generations left to play before pausing again, none when playing freely
+#[derive(Default)]
+pub struct RemainingGenerations(pub Option<u64>);
+
+// the number of generations played by the run button
+pub struct RunGenerations(pub u64);
+
+impl Default for RunGenerations {
+    fn default() -> Self {
+        Self(10)
+    }
+}
+
+// the number of generations to skip when fast-forwarding
+pub struct FastForwardGenerations(pub u64);
+
+impl Default for FastForwardGenerations {
+    fn default() -> Self {
+        Self(1024)
+    }
+}
+
+// the generations a fast-forward without hashlife still has to step through, a few each frame
+#[derive(Default)]
+pub struct SteppedGenerations(pub u64);
+
+// why the last fast-forward couldn't be made, shown next to the button until the next one
+#[derive(Default)]
+pub struct FastForwardError(pub Option<String>);
+
+// the number of cells around the cursor the vaccine brush reaches in each direction
+#[derive(Default)]
+pub struct VaccineBrush(pub usize);
+
 // define wrapper for debugging cells that undergo change
 #[derive(Default)]
 pub struct History(pub Vec<String>);
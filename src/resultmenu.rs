@@ -3,9 +3,10 @@ use bevy_egui::{egui, EguiContext};
 use chrono::Local;
 use std::{fs::File, io::prelude::*};
 
+use engine::{cells::CellState, ending::EndDetector, simulation::Simulation};
+
 use crate::{
-    components::CellState,
-    resources::{CellStates, QuestionnarieResponse, Questions},
+    resources::{QuestionnarieResponse, Questions},
     GameState,
 };
 
@@ -24,43 +25,95 @@ impl Plugin for ResultMenuPlugin {
     }
 }
 
-fn result_menu_system(mut egui_ctx: ResMut<EguiContext>, cell_states: Res<CellStates>) {
+fn result_menu_system(
+    mut egui_ctx: ResMut<EguiContext>,
+    simulation: Res<Simulation>,
+    mut detector: ResMut<EndDetector>,
+) {
+    // find out whether the game ended by itself, taking in any edits made since the last step
+    detector.observe(&simulation);
+    let ending = detector.ending(&simulation, simulation.options.max_period);
     // count the number cells in various states
     let mut alive_count = 0.;
     let mut infected_count = 0.;
-    for i in 0..cell_states.0.len() {
-        for j in 0..cell_states.0[i].len() {
-            match cell_states.0[i][j] {
-                CellState::Alive => alive_count += 1.,
-                CellState::Infected => infected_count += 1.,
-                _ => (),
-            }
+    let mut recovered_count = 0.;
+    let mut exposed_count = 0.;
+    let mut vaccinated_count = 0.;
+    for state in simulation.states() {
+        match state {
+            CellState::Alive => alive_count += 1.,
+            CellState::Infected => infected_count += 1.,
+            CellState::Recovered => recovered_count += 1.,
+            CellState::Exposed => exposed_count += 1.,
+            CellState::Vaccinated => vaccinated_count += 1.,
+            _ => (),
         }
     }
-    let proportion: f32 = infected_count / (alive_count + infected_count);
+    let proportion: f32 = infected_count
+        / (alive_count + infected_count + recovered_count + exposed_count + vaccinated_count);
+    let counts = format!(
+        "alive: {}, vaccinated: {}, exposed: {}, infected: {}, recovered: {}",
+        alive_count, vaccinated_count, exposed_count, infected_count, recovered_count
+    );
+    // count the infected cells carrying each strain still around, once the infection has mutated
+    let strains: Vec<String> = if simulation.strained() {
+        simulation
+            .strains()
+            .iter()
+            .zip(simulation.strain_counts())
+            .filter(|&(_, count)| count > 0)
+            .map(|((id, strain), count)| {
+                format!(
+                    "strain {}: {} infected (virulence: {}, lethality: {:.2})",
+                    id, count, strain.virulence, strain.lethality
+                )
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
     // display the percentage of infected to alive cells in a floating window
     egui::Window::new("Result Menu").show(egui_ctx.ctx_mut(), |ui| {
         ui.heading("Conclusion:");
+        match ending {
+            Some(ending) => ui.label(format!(
+                "{} after {} generations.",
+                ending, simulation.generation
+            )),
+            None => ui.label(format!(
+                "The game was stopped after {} generations.",
+                simulation.generation
+            )),
+        };
         if proportion > 0.5 {
             ui.label(format!(
-                "{:.2}% are infected, therefore we are in a pandemic (alive: {}, infected: {})",
+                "{:.2}% are infected, therefore we are in a pandemic ({})",
                 proportion * 100.,
-                alive_count,
-                infected_count
+                counts
             ));
         } else if !proportion.is_nan() {
             ui.label(format!(
-                "{:.2}% are infected, therefore we are not in a pandemic (alive: {}, infected: {})",
+                "{:.2}% are infected, therefore we are not in a pandemic ({})",
                 proportion * 100.,
-                alive_count,
-                infected_count
+                counts
             ));
         } else {
             ui.label(format!(
-                "It is unknown if we are in a pandemic (alive: {}, infected: {})",
-                alive_count, infected_count,
+                "It is unknown if we are in a pandemic ({})",
+                counts
             ));
         }
+        if simulation.strained() {
+            ui.heading("Strains:");
+            ui.label(format!(
+                "{} strains arose, {} are still infecting cells.",
+                simulation.strains().count(),
+                strains.len()
+            ));
+            for strain in &strains {
+                ui.label(strain);
+            }
+        }
     });
 }
 
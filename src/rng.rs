@@ -0,0 +1,250 @@
+use crate::{cells::CellState, cells::CellStates};
+
+// the distance between the points the clustered pattern's densities are picked at
+pub const CLUSTER_SPACING: usize = 8;
+
+// a small seeded random number generator (splitmix64), so the same seed always gives the same grid
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // a number from 0 up to but not including 1
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // a number from 0 up to but not including n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+// a random number picked by a seed, a generation and a position rather than drawn in
+// turn, so a cell rolls the same whichever order or thread the grid is stepped in
+pub fn roll(seed: u64, generation: u64, (x, y): (i64, i64)) -> u64 {
+    [generation, x as u64, y as u64]
+        .into_iter()
+        .fold(seed, |state, value| Rng::new(state ^ value).next_u64())
+}
+
+// define how randomly placed cells are spread over the grid
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Pattern {
+    // every cell is picked on its own
+    #[default]
+    Uniform,
+    // cells gather in patches, with the same densities overall
+    Clustered,
+    // a healthy population with a single infected cell in it
+    PatientZero,
+}
+
+// the settings of the randomise tool
+#[derive(Clone, Debug, PartialEq)]
+pub struct RandomFill {
+    // the share of the grid that starts alive and infected
+    pub alive: f32,
+    pub infected: f32,
+    pub seed: u64,
+    pub pattern: Pattern,
+}
+
+impl Default for RandomFill {
+    fn default() -> Self {
+        Self {
+            alive: 0.3,
+            infected: 0.05,
+            seed: 0,
+            pattern: Pattern::default(),
+        }
+    }
+}
+
+impl RandomFill {
+    // fill a grid of the given size with randomly placed living and infected cells
+    pub fn generate(&self, width: usize, height: usize) -> CellStates {
+        let mut rng = Rng::new(self.seed);
+        let mut cells = CellStates::new(width, height);
+        let alive = self.alive.clamp(0., 1.);
+        let infected = self.infected.clamp(0., 1. - alive);
+        match self.pattern {
+            Pattern::Uniform => {
+                for y in 0..height {
+                    for x in 0..width {
+                        cells.set(x, y, pick(rng.next_f32(), alive, infected));
+                    }
+                }
+            }
+            Pattern::Clustered => {
+                // the living and infected cells gather separately, so outbreaks start in hotspots
+                let alive_field = Field::new(&mut rng, width, height);
+                let infected_field = Field::new(&mut rng, width, height);
+                for y in 0..height {
+                    for x in 0..width {
+                        // the fields average a half, so doubling them keeps the densities
+                        let infected = (infected * 2. * infected_field.get(x, y)).min(1.);
+                        let alive = (alive * 2. * alive_field.get(x, y)).min(1. - infected);
+                        cells.set(x, y, pick(rng.next_f32(), alive, infected));
+                    }
+                }
+            }
+            Pattern::PatientZero => {
+                for y in 0..height {
+                    for x in 0..width {
+                        cells.set(x, y, pick(rng.next_f32(), alive, 0.));
+                    }
+                }
+                // infect one of the living cells, or any cell if none are alive
+                let living: Vec<usize> = cells
+                    .states()
+                    .enumerate()
+                    .filter(|&(_, state)| state == CellState::Alive)
+                    .map(|(i, _)| i)
+                    .collect();
+                let size = width * height;
+                if size > 0 {
+                    let i = if living.is_empty() {
+                        rng.below(size)
+                    } else {
+                        living[rng.below(living.len())]
+                    };
+                    cells.set(i % width, i / width, CellState::Infected);
+                }
+            }
+        }
+        cells
+    }
+}
+
+// choose the state of a cell from a random number and the chances of it being alive or infected
+fn pick(roll: f32, alive: f32, infected: f32) -> CellState {
+    if roll < infected {
+        CellState::Infected
+    } else if roll < infected + alive {
+        CellState::Alive
+    } else {
+        CellState::Dead
+    }
+}
+
+// random values at points spaced across the grid, blended smoothly in between
+struct Field {
+    columns: usize,
+    values: Vec<f32>,
+}
+
+impl Field {
+    fn new(rng: &mut Rng, width: usize, height: usize) -> Self {
+        let columns = width / CLUSTER_SPACING + 2;
+        let rows = height / CLUSTER_SPACING + 2;
+        Self {
+            columns,
+            values: (0..columns * rows).map(|_| rng.next_f32()).collect(),
+        }
+    }
+
+    fn get(&self, x: usize, y: usize) -> f32 {
+        let (column, row) = (x / CLUSTER_SPACING, y / CLUSTER_SPACING);
+        let spacing = CLUSTER_SPACING as f32;
+        let (tx, ty) = (
+            (x % CLUSTER_SPACING) as f32 / spacing,
+            (y % CLUSTER_SPACING) as f32 / spacing,
+        );
+        let value = |dx, dy| self.values[(row + dy) * self.columns + column + dx];
+        let top = value(0, 0) * (1. - tx) + value(1, 0) * tx;
+        let bottom = value(0, 1) * (1. - tx) + value(1, 1) * tx;
+        top * (1. - ty) + bottom * ty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{edits::EditHistory, simulation::Simulation};
+
+    fn count(cells: &CellStates, state: CellState) -> usize {
+        cells.states().filter(|&s| s == state).count()
+    }
+
+    #[test]
+    fn seeds_are_reproducible() {
+        let mut fill = RandomFill::default();
+        for pattern in [Pattern::Uniform, Pattern::Clustered, Pattern::PatientZero] {
+            fill.pattern = pattern;
+            fill.seed = 7;
+            let cells = fill.generate(40, 30);
+            assert_eq!(fill.generate(40, 30), cells);
+            fill.seed = 8;
+            assert_ne!(fill.generate(40, 30), cells);
+        }
+    }
+
+    #[test]
+    fn densities_are_kept() {
+        let fill = RandomFill {
+            alive: 0.4,
+            infected: 0.1,
+            seed: 3,
+            pattern: Pattern::Uniform,
+        };
+        for pattern in [Pattern::Uniform, Pattern::Clustered] {
+            let cells = RandomFill {
+                pattern,
+                ..fill.clone()
+            }
+            .generate(200, 200);
+            let size = 200. * 200.;
+            let alive = count(&cells, CellState::Alive) as f32 / size;
+            let infected = count(&cells, CellState::Infected) as f32 / size;
+            assert!((alive - 0.4).abs() < 0.05, "{:?} alive {}", pattern, alive);
+            assert!(
+                (infected - 0.1).abs() < 0.03,
+                "{:?} infected {}",
+                pattern,
+                infected
+            );
+        }
+    }
+
+    #[test]
+    fn patient_zero_is_alone() {
+        let fill = RandomFill {
+            pattern: Pattern::PatientZero,
+            ..RandomFill::default()
+        };
+        let cells = fill.generate(50, 50);
+        assert_eq!(count(&cells, CellState::Infected), 1);
+        assert!(count(&cells, CellState::Alive) > 0);
+        // an empty population still gets its patient
+        let empty = RandomFill { alive: 0., ..fill }.generate(5, 5);
+        assert_eq!(count(&empty, CellState::Infected), 1);
+    }
+
+    #[test]
+    fn filling_the_grid_is_undone() {
+        let mut simulation = Simulation::default();
+        simulation.set(0, 0, CellState::Infected);
+        let before = simulation.cells.clone();
+        let (width, height) = simulation.cells.size();
+        let cells = RandomFill::default().generate(width, height);
+        let mut history = EditHistory::default();
+        history.record(simulation.fill(&cells));
+        assert_eq!(simulation.cells, cells);
+        history.undo(&mut simulation);
+        assert_eq!(simulation.cells, before);
+    }
+}
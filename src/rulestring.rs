@@ -0,0 +1,306 @@
+use std::{fmt, str::FromStr};
+
+use crate::options::{Options, RuleKind, Rules};
+
+// the rules of the game written in golly style B/S notation, e.g. B36/S23,
+// with an optional infection part giving the virulence, e.g. B3/S23/I2
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rulestring {
+    pub birth: Rules,
+    pub survival: Rules,
+    pub virulence: Option<u8>,
+}
+
+// define the ways reading a rulestring can fail
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RulestringError {
+    // a part that doesn't start with B, S or I
+    UnknownPart(String),
+    // the same part given more than once
+    RepeatedPart(char),
+    // a part the rulestring has to have
+    MissingPart(char),
+    // a neighbour count that isn't a number
+    InvalidNumber(String),
+}
+
+impl fmt::Display for RulestringError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownPart(part) => write!(f, "Unknown part \"{}\", expected B, S or I", part),
+            Self::RepeatedPart(c) => write!(f, "The {} part is given more than once", c),
+            Self::MissingPart(c) => write!(f, "The {} part is missing", c),
+            Self::InvalidNumber(n) => write!(f, "\"{}\" is not a number of neighbours", n),
+        }
+    }
+}
+
+impl std::error::Error for RulestringError {}
+
+impl Rulestring {
+    // describe the rules currently in use
+    pub fn from_options(options: &Options) -> Self {
+        Self {
+            birth: options.dead_rule.clone(),
+            survival: options.living_rule.clone(),
+            virulence: Some(options.virulence),
+        }
+    }
+
+    // write the rules into the options, keeping the virulence if none was given
+    pub fn apply(self, options: &mut Options) {
+        options.dead_rule = self.birth;
+        options.living_rule = self.survival;
+        if let Some(virulence) = self.virulence {
+            options.virulence = virulence;
+        }
+    }
+}
+
+impl FromStr for Rulestring {
+    type Err = RulestringError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut birth, mut survival, mut virulence) = (None, None, None);
+        for part in s.trim().split('/') {
+            let part = part.trim();
+            let mut chars = part.chars();
+            let letter = chars.next().map(|c| c.to_ascii_uppercase());
+            let slot = match letter {
+                Some('B') => &mut birth,
+                Some('S') => &mut survival,
+                Some('I') => {
+                    if virulence.is_some() {
+                        return Err(RulestringError::RepeatedPart('I'));
+                    }
+                    let value = chars.as_str().trim();
+                    virulence = Some(
+                        value
+                            .parse()
+                            .map_err(|_| RulestringError::InvalidNumber(value.to_string()))?,
+                    );
+                    continue;
+                }
+                _ => return Err(RulestringError::UnknownPart(part.to_string())),
+            };
+            if slot.is_some() {
+                return Err(RulestringError::RepeatedPart(letter.unwrap()));
+            }
+            *slot = Some(parse_counts(chars.as_str())?);
+        }
+        let birth = birth.ok_or(RulestringError::MissingPart('B'))?;
+        let survival = survival.ok_or(RulestringError::MissingPart('S'))?;
+        Ok(Self {
+            birth: to_rule(birth, RuleKind::Dead),
+            survival: to_rule(survival, RuleKind::Living),
+            virulence,
+        })
+    }
+}
+
+impl fmt::Display for Rulestring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "B{}/S{}",
+            format_counts(&self.birth.neighbours(RuleKind::Dead)),
+            format_counts(&self.survival.neighbours(RuleKind::Living))
+        )?;
+        if let Some(virulence) = self.virulence {
+            write!(f, "/I{}", virulence)?;
+        }
+        Ok(())
+    }
+}
+
+// read the neighbour counts of a part, either single digits (e.g. 236)
+// or comma separated numbers and ranges for larger neighbourhoods (e.g. 2,3,10-12)
+fn parse_counts(s: &str) -> Result<Vec<u8>, RulestringError> {
+    let s = s.trim();
+    let mut counts = Vec::new();
+    if s.contains(',') || s.contains('-') {
+        for item in s.split(',') {
+            let item = item.trim();
+            let invalid = || RulestringError::InvalidNumber(item.to_string());
+            match item.split_once('-') {
+                Some((min, max)) => {
+                    let min: u8 = min.trim().parse().map_err(|_| invalid())?;
+                    let max: u8 = max.trim().parse().map_err(|_| invalid())?;
+                    // a range the wrong way round would otherwise quietly match nothing
+                    if min > max {
+                        return Err(invalid());
+                    }
+                    counts.extend(min..=max);
+                }
+                None => counts.push(item.parse().map_err(|_| invalid())?),
+            }
+        }
+    } else {
+        for c in s.chars() {
+            match c.to_digit(10) {
+                Some(digit) => counts.push(digit as u8),
+                None => return Err(RulestringError::InvalidNumber(c.to_string())),
+            }
+        }
+    }
+    counts.sort_unstable();
+    counts.dedup();
+    Ok(counts)
+}
+
+// write neighbour counts as digits, or comma separated once a count needs two digits
+fn format_counts(counts: &[u8]) -> String {
+    if counts.iter().all(|&n| n < 10) {
+        counts.iter().map(|n| n.to_string()).collect()
+    } else {
+        let counts: Vec<String> = counts.iter().map(|n| n.to_string()).collect();
+        counts.join(",")
+    }
+}
+
+// pick the simplest rule that matches exactly the given neighbour counts
+fn to_rule(counts: Vec<u8>, kind: RuleKind) -> Rules {
+    if counts == kind.default_neighbours() {
+        Rules::Default
+    } else if counts.len() > 1
+        && (counts[counts.len() - 1] - counts[0]) as usize == counts.len() - 1
+    {
+        Rules::Range {
+            min: counts[0],
+            max: counts[counts.len() - 1],
+        }
+    } else {
+        Rules::Singles(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cells::CellState,
+        options::{validate_virulence, RuleError},
+        simulation::cell_check,
+    };
+
+    fn round_trip(s: &str) -> String {
+        s.parse::<Rulestring>().unwrap().to_string()
+    }
+
+    #[test]
+    fn default_options_round_trip() {
+        let rulestring = Rulestring::from_options(&Options::default());
+        assert_eq!(rulestring.to_string(), "B3/S23/I2");
+        let mut options = Options {
+            virulence: 5,
+            living_rule: Rules::Singles(vec![1]),
+            ..Options::default()
+        };
+        rulestring.apply(&mut options);
+        assert!(options == Options::default());
+    }
+
+    #[test]
+    fn well_known_rules_round_trip() {
+        for s in [
+            "B3/S23",
+            "B36/S23",
+            "B2/S",
+            "B3678/S34678",
+            "B1357/S1357",
+            "B3/S012345678",
+            "B3/S23/I0",
+            "B36/S125/I4",
+        ] {
+            assert_eq!(round_trip(s), s);
+        }
+    }
+
+    #[test]
+    fn large_neighbourhood_rules_round_trip() {
+        assert_eq!(round_trip("B3,10,11/S2-3"), "B3,10,11/S23");
+        assert_eq!(round_trip("B5-7/S10-12"), "B567/S10,11,12");
+        // every possible count still makes a range
+        let rulestring: Rulestring = "B0-255/S2".parse().unwrap();
+        assert_eq!(rulestring.birth, Rules::Range { min: 0, max: 255 });
+    }
+
+    #[test]
+    fn rulestrings_are_read_loosely() {
+        assert_eq!(round_trip(" s32 / b3 "), "B3/S23");
+        assert_eq!(round_trip("B63/S32/i3"), "B36/S23/I3");
+    }
+
+    #[test]
+    fn rules_are_mapped_onto_options() {
+        let rulestring: Rulestring = "B36/S1357/I4".parse().unwrap();
+        assert_eq!(rulestring.birth, Rules::Singles(vec![3, 6]));
+        assert_eq!(rulestring.survival, Rules::Singles(vec![1, 3, 5, 7]));
+        assert_eq!(rulestring.virulence, Some(4));
+        let rulestring: Rulestring = "B3/S2345".parse().unwrap();
+        assert_eq!(rulestring.birth, Rules::Default);
+        assert_eq!(rulestring.survival, Rules::Range { min: 2, max: 5 });
+        assert_eq!(rulestring.virulence, None);
+    }
+
+    #[test]
+    fn options_round_trip() {
+        let options = Options {
+            living_rule: Rules::Range { min: 1, max: 4 },
+            dead_rule: Rules::Singles(vec![3, 6, 8]),
+            virulence: 3,
+            ..Options::default()
+        };
+        let s = Rulestring::from_options(&options).to_string();
+        assert_eq!(s, "B368/S1234/I3");
+        let mut parsed = Options::default();
+        s.parse::<Rulestring>().unwrap().apply(&mut parsed);
+        assert!(parsed == options);
+    }
+
+    #[test]
+    fn virulence_beyond_the_neighbourhood_is_refused() {
+        let rulestring: Rulestring = "B3/S23/I255".parse().unwrap();
+        let mut options = Options::default();
+        let max = options.max_neighbours();
+        assert_eq!(
+            rulestring.virulence.map(|v| validate_virulence(v, max)),
+            Some(Err(RuleError::TooVirulent { value: 255, max: 8 }))
+        );
+        assert_eq!(validate_virulence(8, max), Ok(()));
+        // an infected cell is still worked out if such a virulence gets through
+        rulestring.apply(&mut options);
+        assert_eq!(
+            cell_check(CellState::Infected, 8, 0, &options),
+            CellState::Dead
+        );
+    }
+
+    #[test]
+    fn invalid_rulestrings_are_rejected() {
+        assert_eq!(
+            "B3/S23/X1".parse::<Rulestring>(),
+            Err(RulestringError::UnknownPart("X1".to_string()))
+        );
+        assert_eq!(
+            "B3/B4/S23".parse::<Rulestring>(),
+            Err(RulestringError::RepeatedPart('B'))
+        );
+        assert_eq!(
+            "S23".parse::<Rulestring>(),
+            Err(RulestringError::MissingPart('B'))
+        );
+        assert_eq!(
+            "B3a/S23".parse::<Rulestring>(),
+            Err(RulestringError::InvalidNumber("a".to_string()))
+        );
+        assert_eq!(
+            "B3/S23/Ix".parse::<Rulestring>(),
+            Err(RulestringError::InvalidNumber("x".to_string()))
+        );
+        assert_eq!(
+            "B5-3/S23".parse::<Rulestring>(),
+            Err(RulestringError::InvalidNumber("5-3".to_string()))
+        );
+    }
+}
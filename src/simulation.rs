@@ -0,0 +1,2767 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    cells::{decode, encode, Cell, CellState, CellStates, Planes},
+    edits::{CellChange, Edit},
+    hashlife::{HashLife, LifeRule, PatternTooLarge},
+    options::{
+        Boundary, GridLayout, Immunity, Infection, Neighbourhood, Options, Pathogen, RuleKind,
+        Rules, Vaccination,
+    },
+    rng::roll,
+    strains::{Strains, MUTATED},
+    timeline::Snapshot,
+    world::{World, CHUNK_SIZE},
+};
+
+// the six neighbours of a cell in a hex grid, odd rows sit half a cell to the right
+pub const HEX_EVEN_ROW: [(isize, isize); 6] = [(-1, 0), (1, 0), (-1, -1), (0, -1), (-1, 1), (0, 1)];
+pub const HEX_ODD_ROW: [(isize, isize); 6] = [(-1, 0), (1, 0), (0, -1), (1, -1), (0, 1), (1, 1)];
+
+// the offsets of the neighbours of a cell
+type Offsets = Vec<(isize, isize)>;
+
+// grids with fewer cells than this are quicker to step on a single thread
+pub const PARALLEL_MIN_CELLS: usize = 128 * 128;
+
+// the game of life engine, kept free of the game engine so it can run headless
+pub struct Simulation {
+    pub cells: CellStates,
+    pub options: Options,
+    // the number of generations that have passed since the grid was last cleared
+    pub generation: u64,
+    // the number of threads large grids are stepped on
+    pub threads: usize,
+    // the endless plane the grid is a view of when the boundary is unbounded
+    pub world: World,
+    // the position in the world of the top left cell of the grid
+    pub origin: (i64, i64),
+    // every strain of the infection that has arisen since the grid was last cleared
+    strains: Strains,
+    // the buffer the next generation is written into before being copied in
+    next: Vec<Cell>,
+    // the bitplanes the next generation is packed into
+    next_planes: Planes<Vec<u64>>,
+    // the rules compiled from the options, rebuilt whenever the options change
+    table: RuleTable,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new(CellStates::default(), Options::default())
+    }
+}
+
+impl Simulation {
+    pub fn new(cells: CellStates, options: Options) -> Self {
+        let options = with_storable_boundary(options);
+        let world = if options.boundary == Boundary::Unbounded {
+            World::from_cells(&cells, (0, 0))
+        } else {
+            World::default()
+        };
+        Self {
+            table: RuleTable::new(&options),
+            strains: Strains::new(options.virulence, options.pathogen.lethality),
+            cells,
+            options,
+            generation: 0,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            world,
+            origin: (0, 0),
+            next: Vec::new(),
+            next_planes: (Vec::new(), Vec::new(), Vec::new()),
+        }
+    }
+
+    // recompile the rules if the options were changed since they were last used
+    fn compile(&mut self) {
+        if self.table.options() != &self.options {
+            self.table = RuleTable::new(&self.options);
+            // timers left over from options that needed them would tell apart equal grids
+            if !self.options.timed() {
+                self.cells.clear_timers();
+                self.world.clear_timers();
+            }
+            self.strains
+                .set_first(self.options.virulence, self.options.pathogen.lethality);
+            if !self.strained() {
+                self.cells.clear_strains();
+                self.world.clear_strains();
+            }
+        }
+        // strains that arose in the last step need their own rules
+        self.table.include(&self.strains, self.strained());
+    }
+
+    // whether cells have to keep track of the strain they carry
+    pub fn strained(&self) -> bool {
+        self.options.pathogen.mutation > 0. || self.strains.count() > 1
+    }
+
+    // every strain that has arisen since the grid was last cleared
+    pub fn strains(&self) -> &Strains {
+        &self.strains
+    }
+
+    // the number of cells infected with each strain, in the order the strains arose
+    pub fn strain_counts(&self) -> Vec<usize> {
+        let known = self.strains.count();
+        let mut counts = vec![0; known];
+        let mut count = |cells: &CellStates| {
+            for y in 0..cells.height() {
+                for x in 0..cells.width() {
+                    if cells.get(x, y) == CellState::Infected {
+                        let strain = cells.strain(x, y) as usize;
+                        counts[if strain < known { strain } else { 0 }] += 1;
+                    }
+                }
+            }
+        };
+        if self.unbounded() {
+            self.world.chunk_cells().for_each(count);
+        } else {
+            count(&self.cells);
+        }
+        counts
+    }
+
+    // number the strains that arose in the last step, in order of position so the
+    // numbers are the same whichever order or thread the grid was stepped in
+    fn number_strains(&mut self) {
+        let mut mutated: Vec<((i64, i64), u16)> = if self.unbounded() {
+            self.world.mutated().collect()
+        } else {
+            self.cells
+                .mutated()
+                .map(|((x, y), strain)| {
+                    ((self.origin.0 + x as i64, self.origin.1 + y as i64), strain)
+                })
+                .collect()
+        };
+        mutated.sort_unstable_by_key(|&((x, y), _)| (y, x));
+        let max_virulence = self.options.max_neighbours();
+        for (position, strain) in mutated {
+            let roll = roll(
+                self.options.pathogen.seed ^ TRAIT_SALT,
+                self.generation,
+                position,
+            );
+            let strain = self.strains.mutate(strain & !MUTATED, roll, max_virulence);
+            self.set_strain_at(position, strain);
+        }
+    }
+
+    // change the strain of a cell at its position in the world, and on the grid if it's in view
+    fn set_strain_at(&mut self, (x, y): (i64, i64), strain: u16) {
+        if self.unbounded() {
+            self.world.set_strain(x, y, strain);
+        }
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        let (width, height) = self.cells.size();
+        if x >= 0 && y >= 0 && x < width as i64 && y < height as i64 {
+            self.cells.set_strain(x as usize, y as usize, strain);
+        }
+    }
+
+    // advance the grid by one generation, every cell sees its neighbours as they were
+    pub fn step(&mut self) {
+        self.compile();
+        let (width, height) = self.cells.size();
+        // split large grids into bands of rows stepped side by side, each
+        // cell only depends on the previous generation so the result is the same
+        let threads = if width * height >= PARALLEL_MIN_CELLS {
+            self.threads.clamp(1, height)
+        } else {
+            1
+        };
+        if self.unbounded() {
+            self.step_world();
+        } else if self.packed() {
+            self.step_packed(threads);
+        } else {
+            self.step_cells(threads);
+        }
+        if self.options.pathogen.mutation > 0. {
+            self.number_strains();
+        }
+        self.generation += 1;
+    }
+
+    // advance the grid by many generations, skipping through them with hashlife
+    // when the game has been reduced to plain life on an endless plane,
+    // leaving it as it was if the pattern grows too large to skip
+    pub fn fast_forward(&mut self, generations: u64) -> Result<(), PatternTooLarge> {
+        self.compile();
+        match self.hashlife_rule() {
+            Some(rule) => {
+                let mut life = HashLife::from_world(&self.world, rule);
+                life.advance(generations)?;
+                self.world = life.to_world();
+                self.refresh_view();
+                self.generation += generations;
+            }
+            None => {
+                for _ in 0..generations {
+                    self.step();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the life-like rule the game has been reduced to, if it can be fast-forwarded with
+    // hashlife, needing an endless square grid of eight neighbours without infected cells
+    pub fn hashlife_rule(&self) -> Option<LifeRule> {
+        if !self.unbounded()
+            || self.options.layout != GridLayout::Square
+            || self.options.neighbourhood != Neighbourhood::Moore(1)
+            || self.world.has_infected()
+        {
+            return None;
+        }
+        if self.table.options() == &self.options {
+            self.table.life_rule()
+        } else {
+            RuleTable::new(&self.options).life_rule()
+        }
+    }
+
+    // roughly how many cells a step works out, the stored chunks of the world when unbounded
+    pub fn stepped_cells(&self) -> usize {
+        if self.unbounded() {
+            self.world.chunks() * CHUNK_SIZE * CHUNK_SIZE
+        } else {
+            self.cells.width() * self.cells.height()
+        }
+    }
+
+    // step the whole world, then copy the part the grid shows back into it
+    fn step_world(&mut self) {
+        let (even_row, odd_row) = self.offsets();
+        let threads = if self.stepped_cells() >= PARALLEL_MIN_CELLS {
+            self.threads.max(1)
+        } else {
+            1
+        };
+        self.world = self.world.step(
+            &self.table,
+            &self.strains,
+            &even_row,
+            &odd_row,
+            self.generation,
+            threads,
+        );
+        self.refresh_view();
+    }
+
+    // whether the grid is a view of an endless plane
+    pub fn unbounded(&self) -> bool {
+        self.options.boundary == Boundary::Unbounded
+    }
+
+    // change the options, moving the pattern into or out of the world when the
+    // grid starts or stops being unbounded
+    pub fn set_options(&mut self, options: Options) {
+        let was_unbounded = self.unbounded();
+        self.options = with_storable_boundary(options);
+        if self.unbounded() && !was_unbounded {
+            self.world = World::from_cells(&self.cells, self.origin);
+        } else if !self.unbounded() && was_unbounded {
+            self.world = World::default();
+        }
+    }
+
+    // change the state of a cell on the grid, and in the world behind it
+    pub fn set(&mut self, x: usize, y: usize, state: CellState) {
+        self.cells.set(x, y, state);
+        if self.unbounded() {
+            self.world
+                .set(self.origin.0 + x as i64, self.origin.1 + y as i64, state);
+        }
+    }
+
+    // change a cell on the grid, returning the edit so it can be undone
+    pub fn edit(&mut self, x: usize, y: usize, state: CellState) -> Edit {
+        let before = self.cells.cell(x, y);
+        // a cell left in the same state keeps its timer and strain
+        let changes = if before.state == state {
+            Vec::new()
+        } else {
+            self.set(x, y, state);
+            vec![CellChange {
+                position: (self.origin.0 + x as i64, self.origin.1 + y as i64),
+                before,
+                after: self.cells.cell(x, y),
+            }]
+        };
+        Edit {
+            changes,
+            generations: (self.generation, self.generation),
+        }
+    }
+
+    // replace every cell on the grid, returning the edit so it can be undone
+    pub fn fill(&mut self, cells: &CellStates) -> Edit {
+        let mut edit = Edit {
+            changes: Vec::new(),
+            generations: (self.generation, self.generation),
+        };
+        let (width, height) = self.cells.size();
+        for y in 0..height.min(cells.height()) {
+            for x in 0..width.min(cells.width()) {
+                edit.changes
+                    .extend(self.edit(x, y, cells.get(x, y)).changes);
+            }
+        }
+        edit
+    }
+
+    // vaccinate the living cells in the square reaching radius cells out from a cell,
+    // returning the edit so it can be undone
+    pub fn vaccinate(&mut self, x: usize, y: usize, radius: usize) -> Edit {
+        let mut edit = Edit {
+            changes: Vec::new(),
+            generations: (self.generation, self.generation),
+        };
+        let (width, height) = self.cells.size();
+        for y in y.saturating_sub(radius)..(y + radius + 1).min(height) {
+            for x in x.saturating_sub(radius)..(x + radius + 1).min(width) {
+                if self.cells.get(x, y) == CellState::Alive {
+                    edit.changes
+                        .extend(self.edit(x, y, CellState::Vaccinated).changes);
+                }
+            }
+        }
+        edit
+    }
+
+    // the cell at its position in the world, anything beyond a grid with edges is dead
+    pub fn get_at(&self, (x, y): (i64, i64)) -> Cell {
+        if self.unbounded() {
+            return self.world.cell(x, y);
+        }
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        let (width, height) = self.cells.size();
+        if x >= 0 && y >= 0 && x < width as i64 && y < height as i64 {
+            self.cells.cell(x as usize, y as usize)
+        } else {
+            Cell::default()
+        }
+    }
+
+    // change a cell at its position in the world, showing it on the grid if it's in view
+    pub fn set_at(&mut self, (x, y): (i64, i64), cell: Cell) {
+        if self.unbounded() {
+            self.world.set_cell(x, y, cell);
+        }
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        let (width, height) = self.cells.size();
+        if x >= 0 && y >= 0 && x < width as i64 && y < height as i64 {
+            self.cells.set_cell(x as usize, y as usize, cell);
+        }
+    }
+
+    // kill every cell and start counting generations again, returning the edit so it can be undone
+    pub fn clear(&mut self) -> Edit {
+        // the living and infected cells at their positions in the world
+        let occupied: Vec<(i64, i64)> = if self.unbounded() {
+            self.world.cells().map(|(position, _)| position).collect()
+        } else {
+            let width = self.cells.width();
+            self.cells
+                .states()
+                .enumerate()
+                .filter(|&(_, state)| state != CellState::Dead)
+                .map(|(i, _)| {
+                    let (x, y) = ((i % width) as i64, (i / width) as i64);
+                    (self.origin.0 + x, self.origin.1 + y)
+                })
+                .collect()
+        };
+        let changes = occupied
+            .into_iter()
+            .map(|position| CellChange {
+                position,
+                before: self.get_at(position),
+                after: Cell::default(),
+            })
+            .collect();
+        let edit = Edit {
+            changes,
+            generations: (self.generation, 0),
+        };
+        let (width, height) = self.cells.size();
+        self.cells = CellStates::new(width, height);
+        self.world = World::default();
+        self.generation = 0;
+        self.strains = Strains::new(self.options.virulence, self.options.pathogen.lethality);
+        edit
+    }
+
+    // save the grid as it is now so it can be gone back to
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            generation: self.generation,
+            cells: self.cells.clone(),
+            world: self.unbounded().then(|| self.world.clone()),
+            origin: self.origin,
+            strains: self.strains.clone(),
+        }
+    }
+
+    // whether the grid is still exactly as it was when the snapshot was taken
+    pub fn matches(&self, snapshot: &Snapshot) -> bool {
+        snapshot.generation == self.generation
+            && snapshot.origin == self.origin
+            && snapshot.cells == self.cells
+            && snapshot.strains == self.strains
+            && (!self.unbounded() || snapshot.world.as_ref() == Some(&self.world))
+    }
+
+    // put the grid back the way it was, keeping its current size and options
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        let (width, height) = self.cells.size();
+        self.cells = if snapshot.cells.size() == (width, height) {
+            snapshot.cells.clone()
+        } else {
+            snapshot.cells.resized(width, height)
+        };
+        self.origin = snapshot.origin;
+        self.generation = snapshot.generation;
+        // the strains the cells carry, with the first following the current options
+        self.strains = snapshot.strains.clone();
+        self.strains
+            .set_first(self.options.virulence, self.options.pathogen.lethality);
+        self.world = match (&snapshot.world, self.unbounded()) {
+            (Some(world), true) => world.clone(),
+            (None, true) => World::from_cells(&self.cells, self.origin),
+            (_, false) => World::default(),
+        };
+        if self.unbounded() {
+            self.refresh_view();
+        }
+    }
+
+    // iterate over the state of every cell, across the whole world when unbounded
+    pub fn states(&self) -> Box<dyn Iterator<Item = CellState> + '_> {
+        if self.unbounded() {
+            Box::new(self.world.states())
+        } else {
+            Box::new(self.cells.states())
+        }
+    }
+
+    // whether every cell has died, leaving at most the barriers, across the whole world when unbounded
+    pub fn is_extinct(&self) -> bool {
+        if self.unbounded() {
+            self.world.is_lifeless()
+        } else {
+            self.cells.is_lifeless()
+        }
+    }
+
+    // whether the next generation follows from the grid alone, so a grid seen before goes on
+    // repeating, vaccines that don't always or never work roll for any vaccinated cell
+    pub fn deterministic(&self) -> bool {
+        let efficacy = self.options.vaccination.efficacy;
+        self.options.deterministic()
+            && !self.strained()
+            && (efficacy <= 0.
+                || efficacy >= 1.
+                || self.states().all(|state| state != CellState::Vaccinated))
+    }
+
+    // a hash of the state of every cell, so generations can be compared without keeping them
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        if self.unbounded() {
+            self.world.hash(&mut hasher);
+        } else {
+            self.cells.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    // move an unbounded grid so a position just beyond its edges comes into view,
+    // returning how far the grid moved, hex grids move two rows at a time so
+    // every row keeps its offset
+    pub fn scroll_to(&mut self, x: isize, y: isize) -> (isize, isize) {
+        let (width, height) = self.cells.size();
+        let beyond = |i: isize, n: usize| {
+            if i < 0 {
+                i
+            } else {
+                (i - n as isize + 1).max(0)
+            }
+        };
+        let (mut dx, mut dy) = (beyond(x, width), beyond(y, height));
+        if self.options.layout == GridLayout::Hex && dy % 2 != 0 {
+            dy += dy.signum();
+        }
+        if !self.unbounded() {
+            (dx, dy) = (0, 0);
+        }
+        if (dx, dy) != (0, 0) {
+            self.origin = (self.origin.0 + dx as i64, self.origin.1 + dy as i64);
+            self.refresh_view();
+        }
+        (dx, dy)
+    }
+
+    // copy the part of the world the grid shows into it
+    fn refresh_view(&mut self) {
+        let (width, height) = self.cells.size();
+        self.cells = self.world.view(self.origin, width, height);
+    }
+
+    // whether the grid can be stepped a whole word of cells at a time, which
+    // leaves no room to count how long each cell has been in its state or its strain
+    fn packed(&self) -> bool {
+        !self.options.timed()
+            && !self.strained()
+            && self.options.layout == GridLayout::Square
+            && self.options.neighbourhood == Neighbourhood::Moore(1)
+            && self.options.boundary == Boundary::Dead
+    }
+
+    // step the grid one cell at a time, for any layout, neighbourhood and boundary
+    fn step_cells(&mut self, threads: usize) {
+        let (width, height) = self.cells.size();
+        let (even_row, odd_row) = self.offsets();
+        // make sure the buffer matches the grid in case it was replaced
+        let mut next = std::mem::take(&mut self.next);
+        next.resize(width * height, Cell::default());
+        if threads > 1 {
+            let rows = height.div_ceil(threads);
+            let simulation = &*self;
+            std::thread::scope(|scope| {
+                for (band, out) in next.chunks_mut(rows * width).enumerate() {
+                    let (even_row, odd_row) = (&even_row, &odd_row);
+                    scope.spawn(move || simulation.step_rows(band * rows, out, even_row, odd_row));
+                }
+            });
+        } else {
+            self.step_rows(0, &mut next, &even_row, &odd_row);
+        }
+        // copy the finished generation in
+        self.cells.set_cells(&next);
+        self.next = next;
+    }
+
+    // step the bitplanes directly, counting the neighbours of 64 cells at once
+    fn step_packed(&mut self, threads: usize) {
+        let height = self.cells.height();
+        let words = self.cells.words_per_row();
+        let mut planes = std::mem::take(&mut self.next_planes);
+        for plane in [&mut planes.0, &mut planes.1, &mut planes.2] {
+            plane.resize(words * height, 0);
+        }
+        if threads > 1 {
+            let rows = height.div_ceil(threads);
+            let simulation = &*self;
+            std::thread::scope(|scope| {
+                let bands = planes
+                    .0
+                    .chunks_mut(rows * words)
+                    .zip(planes.1.chunks_mut(rows * words))
+                    .zip(planes.2.chunks_mut(rows * words));
+                for (band, ((alive, infected), extra)) in bands.enumerate() {
+                    scope.spawn(move || {
+                        simulation.step_packed_rows(band * rows, (alive, infected, extra))
+                    });
+                }
+            });
+        } else {
+            self.step_packed_rows(0, (&mut planes.0, &mut planes.1, &mut planes.2));
+        }
+        // swap the finished generation in, keeping the old planes for the next step
+        self.cells.swap_planes(&mut planes);
+        self.next_planes = planes;
+    }
+
+    // work out the next bitplanes of the rows starting at first_row that fit in the planes given
+    fn step_packed_rows(&self, first_row: usize, planes: Planes<&mut [u64]>) {
+        let (width, height) = self.cells.size();
+        let words = self.cells.words_per_row();
+        // a dead cell with no neighbours stays dead under almost every rule,
+        // so empty stretches of the grid can be skipped a word at a time
+        let quiet = self.table.get(CellState::Dead, 0, 0) == CellState::Dead;
+        let empty = vec![0; words];
+        let row = |y: usize| {
+            if y < height {
+                self.cells.row_words(y)
+            } else {
+                (&empty[..], &empty[..], &empty[..])
+            }
+        };
+        let (alive, infected, extra) = planes;
+        let rows = alive
+            .chunks_mut(words)
+            .zip(infected.chunks_mut(words))
+            .zip(extra.chunks_mut(words));
+        for (i, ((next_alive, next_infected), next_extra)) in rows.enumerate() {
+            let y = first_row + i;
+            // the rows above and below, off the grid they count as dead
+            let (above, centre, below) = (row(y.wrapping_sub(1)), row(y), row(y + 1));
+            for k in 0..words {
+                // recovered and exposed cells set the living bit, and count as living neighbours
+                let live = neighbour_counts([above, centre, below], k, |alive, _| alive);
+                let sick = neighbour_counts([above, centre, below], k, |alive, infected| {
+                    infected & !alive
+                });
+                let (state_alive, state_infected, state_extra) =
+                    (centre.0[k], centre.1[k], centre.2[k]);
+                let occupied = live
+                    .iter()
+                    .chain(&sick)
+                    .fold(state_alive | state_infected | state_extra, |a, b| a | b);
+                (next_alive[k], next_infected[k], next_extra[k]) = (0, 0, 0);
+                if quiet && occupied == 0 {
+                    continue;
+                }
+                // the bits past the width are padding and have to stay clear
+                for bit in 0..(width - k * 64).min(64) {
+                    let count = |planes: &[u64; 4]| {
+                        planes
+                            .iter()
+                            .enumerate()
+                            .fold(0, |n, (i, plane)| n | ((plane >> bit) & 1) << i)
+                            as u8
+                    };
+                    let state = decode(
+                        state_alive >> bit,
+                        state_infected >> bit,
+                        state_extra >> bit,
+                    );
+                    let position = (
+                        self.origin.0 + (k * 64 + bit) as i64,
+                        self.origin.1 + y as i64,
+                    );
+                    let neighbours = Neighbours {
+                        live: count(&live),
+                        infected: count(&sick),
+                        source: 0,
+                    };
+                    let cell = Cell {
+                        state,
+                        ..Cell::default()
+                    };
+                    let next =
+                        self.table
+                            .next(&self.strains, cell, neighbours, self.generation, position);
+                    let (a, i, e) = encode(next.state);
+                    next_alive[k] |= a << bit;
+                    next_infected[k] |= i << bit;
+                    next_extra[k] |= e << bit;
+                }
+            }
+        }
+    }
+
+    // work out the next state of the rows starting at first_row that fit in out
+    fn step_rows(
+        &self,
+        first_row: usize,
+        out: &mut [Cell],
+        even_row: &[(isize, isize)],
+        odd_row: &[(isize, isize)],
+    ) {
+        let width = self.cells.width();
+        let walled = self.cells.has_barriers();
+        for (i, row) in out.chunks_mut(width).enumerate() {
+            let y = first_row + i;
+            let offsets = if y.is_multiple_of(2) {
+                even_row
+            } else {
+                odd_row
+            };
+            for (x, next) in row.iter_mut().enumerate() {
+                let (live, infected) = self.count(x, y, offsets, walled);
+                let cell = self.cells.cell(x, y);
+                let position = (self.origin.0 + x as i64, self.origin.1 + y as i64);
+                // the strains of the infected neighbours, in the order of the offsets
+                let strains = offsets.iter().filter_map(|&(dx, dy)| {
+                    if walled && self.hidden(x, y, (dx, dy)) {
+                        return None;
+                    }
+                    let (nx, ny) = self.neighbour(x as isize + dx, y as isize + dy)?;
+                    (self.cells.get(nx, ny) == CellState::Infected)
+                        .then(|| self.cells.strain(nx, ny))
+                });
+                let neighbours = Neighbours {
+                    live,
+                    infected,
+                    source: self.table.source(
+                        cell.state,
+                        infected,
+                        self.generation,
+                        position,
+                        strains,
+                    ),
+                };
+                *next = self
+                    .table
+                    .next(&self.strains, cell, neighbours, self.generation, position);
+            }
+        }
+    }
+
+    // change the size of the grid, keeping the pattern where it fits
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.cells = self.cells.resized(width, height);
+        // an unbounded grid shows more or less of the world instead
+        if self.unbounded() {
+            self.refresh_view();
+        }
+    }
+
+    // count the living and infected cells in the neighbourhood of a cell, stepping uses the counts
+    // kept in the rule table so only the tests need this
+    #[cfg(test)]
+    pub fn count_neighbors(&self, x: usize, y: usize) -> (u8, u8) {
+        let (even_row, odd_row) = self.offsets();
+        self.count(
+            x,
+            y,
+            if y.is_multiple_of(2) {
+                &even_row
+            } else {
+                &odd_row
+            },
+            self.cells.has_barriers(),
+        )
+    }
+
+    // the offsets of the neighbours of cells in even and odd rows
+    fn offsets(&self) -> (Offsets, Offsets) {
+        match self.options.layout {
+            GridLayout::Square => {
+                let offsets = self.options.neighbourhood.offsets();
+                (offsets.clone(), offsets)
+            }
+            GridLayout::Hex => (HEX_EVEN_ROW.to_vec(), HEX_ODD_ROW.to_vec()),
+        }
+    }
+
+    // the cell a neighbour that may lie beyond the edges of the grid lands on
+    fn neighbour(&self, x: isize, y: isize) -> Option<(usize, usize)> {
+        let (width, height) = self.cells.size();
+        match self.options.layout {
+            GridLayout::Square => wrap(self.options.boundary, x, y, width, height),
+            GridLayout::Hex => wrap_hex(self.options.boundary, x, y, width, height),
+        }
+    }
+
+    // whether a barrier stands between a cell and the neighbour at an offset from it
+    fn hidden(&self, x: usize, y: usize, offset: (isize, isize)) -> bool {
+        self.table.sight(offset).is_some_and(|sight| {
+            sight.blocked(|dx, dy| {
+                self.neighbour(x as isize + dx, y as isize + dy)
+                    .is_some_and(|(nx, ny)| self.cells.get(nx, ny) == CellState::Barrier)
+            })
+        })
+    }
+
+    // count the neighbours of a cell, looking out for barriers hiding them if the grid has any
+    fn count(&self, x: usize, y: usize, offsets: &[(isize, isize)], walled: bool) -> (u8, u8) {
+        let mut live_neighbors = 0;
+        let mut infected_neighbors = 0;
+        for &(dx, dy) in offsets {
+            if walled && self.hidden(x, y, (dx, dy)) {
+                continue;
+            }
+            // find the cell the neighbour lands on, if any
+            let (nx, ny) = match self.neighbour(x as isize + dx, y as isize + dy) {
+                Some(coord) => coord,
+                None => continue,
+            };
+            match self.cells.get(nx, ny) {
+                CellState::Alive
+                | CellState::Recovered
+                | CellState::Exposed
+                | CellState::Vaccinated => live_neighbors += 1,
+                CellState::Infected => infected_neighbors += 1,
+                // barriers pass on neither life nor the infection
+                CellState::Dead | CellState::Barrier => (),
+            }
+        }
+        (live_neighbors, infected_neighbors)
+    }
+}
+
+// the next state of a cell for every combination of its state and neighbours
+pub struct RuleTable {
+    // the options the table was compiled from
+    options: Options,
+    // the most neighbours of either kind a cell can have, plus one for none
+    counts: usize,
+    transitions: Vec<CellState>,
+    // the transitions under the virulence of each strain that differs from the options,
+    // indexed by virulence and only compiled once a strain needs them
+    variants: Vec<Option<Vec<CellState>>>,
+    // the rolls that infect a living cell, when infection is left to chance
+    chance: Option<Chance>,
+    vaccine: Vaccine,
+    spread: Spread,
+    // the lines of sight to the neighbours of a square grid that barriers can hide
+    sights: HashMap<(isize, isize), Sight>,
+}
+
+// the neighbours of a cell that decide its next state, and the strain it would
+// catch from them if it caught the infection
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Neighbours {
+    pub live: u8,
+    pub infected: u8,
+    pub source: u16,
+}
+
+// mixed into the seeds of the vaccine's rolls, so they don't line up
+// with the rolls for infection when the same seed is picked for both
+const RESIST_SALT: u64 = 0x5245_5349_5354;
+const CAMPAIGN_SALT: u64 = 0x4341_4d50_4149_474e;
+// and into the seed of the pathogen's rolls, so picking the neighbour a cell catches the
+// infection from, killing it, mutating the infection and picking the traits of the new
+// strain are all rolled for separately
+const SOURCE_SALT: u64 = 0x534f_5552_4345;
+const LETHALITY_SALT: u64 = 0x4c45_5448_414c;
+const MUTATION_SALT: u64 = 0x4d55_5441_5445;
+const TRAIT_SALT: u64 = 0x5452_4149_5453;
+
+// scale a probability up to a u64 so a roll can be compared against it directly
+fn threshold(probability: f64) -> u64 {
+    (probability.clamp(0., 1.) * u64::MAX as f64) as u64
+}
+
+// whether a roll comes in under a threshold, which is always true for a certainty
+fn passes(threshold: u64, roll: u64) -> bool {
+    threshold == u64::MAX || roll < threshold
+}
+
+// the chance of a living cell catching the infection from each number of infected
+// neighbours, scaled up to a u64 so a roll can be compared against it directly
+struct Chance {
+    seed: u64,
+    thresholds: Vec<u64>,
+}
+
+impl Chance {
+    fn new(probability: f32, seed: u64, counts: usize) -> Self {
+        let escape = 1. - probability.clamp(0., 1.) as f64;
+        let thresholds = (0..counts)
+            .map(|infected_neighbors| {
+                // the cell escapes only if every infected neighbour fails to pass it on
+                threshold(1. - escape.powi(infected_neighbors as i32))
+            })
+            .collect();
+        Self { seed, thresholds }
+    }
+
+    fn catches(&self, infected_neighbors: u8, generation: u64, position: (i64, i64)) -> bool {
+        passes(
+            self.thresholds[infected_neighbors as usize],
+            roll(self.seed, generation, position),
+        )
+    }
+}
+
+// the rolls vaccinated cells make to resist the infection, and living cells make to be
+// vaccinated once the campaign has started
+struct Vaccine {
+    seed: u64,
+    efficacy: u64,
+    rate: u64,
+    start: u64,
+}
+
+impl Vaccine {
+    fn new(vaccination: &Vaccination) -> Self {
+        Self {
+            seed: vaccination.seed,
+            efficacy: threshold(vaccination.efficacy as f64),
+            rate: threshold(vaccination.rate as f64),
+            start: vaccination.start,
+        }
+    }
+
+    fn resists(&self, generation: u64, position: (i64, i64)) -> bool {
+        passes(
+            self.efficacy,
+            roll(self.seed ^ RESIST_SALT, generation, position),
+        )
+    }
+
+    fn vaccinates(&self, generation: u64, position: (i64, i64)) -> bool {
+        self.rate > 0
+            && generation >= self.start
+            && passes(
+                self.rate,
+                roll(self.seed ^ CAMPAIGN_SALT, generation, position),
+            )
+    }
+}
+
+// the rolls that pick the strain a cell catches, kill infected cells and mutate the infection
+struct Spread {
+    seed: u64,
+    mutation: u64,
+    // whether cells keep track of the strain they carry, so there's one to pick
+    strained: bool,
+}
+
+impl Spread {
+    fn new(pathogen: &Pathogen) -> Self {
+        Self {
+            seed: pathogen.seed,
+            mutation: threshold(pathogen.mutation as f64),
+            strained: false,
+        }
+    }
+
+    fn kills(&self, lethality: f32, generation: u64, position: (i64, i64)) -> bool {
+        lethality > 0.
+            && passes(
+                threshold(lethality as f64),
+                roll(self.seed ^ LETHALITY_SALT, generation, position),
+            )
+    }
+
+    fn mutates(&self, generation: u64, position: (i64, i64)) -> bool {
+        self.mutation > 0
+            && passes(
+                self.mutation,
+                roll(self.seed ^ MUTATION_SALT, generation, position),
+            )
+    }
+}
+
+// the outcome of every combination of a cell's state and neighbours under the options
+fn transitions(options: &Options, counts: usize) -> Vec<CellState> {
+    let mut transitions = vec![CellState::default(); CellState::ALL.len() * counts * counts];
+    for state in CellState::ALL {
+        for live_neighbors in 0..counts {
+            for infected_neighbors in 0..counts - live_neighbors {
+                transitions
+                    [(state as usize * counts + live_neighbors) * counts + infected_neighbors] =
+                    cell_check(
+                        state,
+                        live_neighbors as u8,
+                        infected_neighbors as u8,
+                        options,
+                    );
+            }
+        }
+    }
+    transitions
+}
+
+impl RuleTable {
+    // work out the outcome of every possible cell once, so each step only has to look it up
+    pub fn new(options: &Options) -> Self {
+        let counts = options.max_neighbours() as usize + 1;
+        let transitions = transitions(options, counts);
+        let chance = match options.infection {
+            Infection::Threshold => None,
+            Infection::Chance { probability, seed } => Some(Chance::new(probability, seed, counts)),
+        };
+        Self {
+            options: options.clone(),
+            counts,
+            transitions,
+            variants: Vec::new(),
+            chance,
+            vaccine: Vaccine::new(&options.vaccination),
+            spread: Spread::new(&options.pathogen),
+            // hexagonal neighbours all touch the cell, so nothing can come between them
+            sights: match options.layout {
+                GridLayout::Square => options
+                    .neighbourhood
+                    .offsets()
+                    .into_iter()
+                    .map(|offset| (offset, Sight::new(offset)))
+                    .filter(|(_, sight)| !sight.is_empty())
+                    .collect(),
+                GridLayout::Hex => HashMap::new(),
+            },
+        }
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    // the line of sight to the neighbour at an offset, none when nothing can hide it
+    pub fn sight(&self, offset: (isize, isize)) -> Option<&Sight> {
+        self.sights.get(&offset)
+    }
+
+    // compile the transitions of any strain that doesn't have them yet
+    pub fn include(&mut self, strains: &Strains, strained: bool) {
+        self.spread.strained = strained;
+        for (_, strain) in strains.iter() {
+            let virulence = strain.virulence as usize;
+            if strain.virulence == self.options.virulence
+                || self.variants.get(virulence).is_some_and(Option::is_some)
+            {
+                continue;
+            }
+            if self.variants.len() <= virulence {
+                self.variants.resize(virulence + 1, None);
+            }
+            let options = Options {
+                virulence: strain.virulence,
+                ..self.options.clone()
+            };
+            self.variants[virulence] = Some(transitions(&options, self.counts));
+        }
+    }
+
+    // look up the next state of a cell
+    pub fn get(&self, state: CellState, live_neighbors: u8, infected_neighbors: u8) -> CellState {
+        self.get_with(
+            self.options.virulence,
+            state,
+            live_neighbors,
+            infected_neighbors,
+        )
+    }
+
+    // look up the next state of a cell under a strain of the given virulence,
+    // falling back on the options' if the strain hasn't been included
+    pub fn get_with(
+        &self,
+        virulence: u8,
+        state: CellState,
+        live_neighbors: u8,
+        infected_neighbors: u8,
+    ) -> CellState {
+        let transitions = match self.variants.get(virulence as usize) {
+            Some(Some(variant)) if virulence != self.options.virulence => variant,
+            _ => &self.transitions,
+        };
+        transitions[(state as usize * self.counts + live_neighbors as usize) * self.counts
+            + infected_neighbors as usize]
+    }
+
+    // the strain a healthy cell would catch, carried by the one of its infected
+    // neighbours picked by a roll from their strains listed in the order of the offsets
+    pub fn source(
+        &self,
+        state: CellState,
+        infected_neighbors: u8,
+        generation: u64,
+        position: (i64, i64),
+        mut strains: impl Iterator<Item = u16>,
+    ) -> u16 {
+        if !self.spread.strained
+            || infected_neighbors == 0
+            || !matches!(state, CellState::Alive | CellState::Vaccinated)
+        {
+            return 0;
+        }
+        let pick =
+            roll(self.spread.seed ^ SOURCE_SALT, generation, position) % infected_neighbors as u64;
+        strains.nth(pick as usize).unwrap_or(0)
+    }
+
+    // the next state, timer and strain of the cell at a position in the world, rolling for
+    // whether a surviving cell catches the infection when it's left to chance, whether a
+    // vaccine holds it off, whether the infection kills the cell and whether a campaign
+    // vaccinates it, then moving exposed, infected and recovered cells on once they've
+    // spent long enough in their state, and marking infections that mutated on catching
+    pub fn next(
+        &self,
+        strains: &Strains,
+        cell: Cell,
+        neighbours: Neighbours,
+        generation: u64,
+        position: (i64, i64),
+    ) -> Cell {
+        let Cell { state, timer, .. } = cell;
+        let Neighbours {
+            live: live_neighbors,
+            infected: infected_neighbors,
+            source,
+        } = neighbours;
+        let healthy = matches!(state, CellState::Alive | CellState::Vaccinated);
+        // cells carrying the infection follow their own strain, healthy cells the one they'd catch
+        let carried = match state {
+            CellState::Infected | CellState::Exposed => cell.strain,
+            _ if healthy => source,
+            _ => 0,
+        };
+        let strain = strains.get(carried);
+        let mut next = self.get_with(strain.virulence, state, live_neighbors, infected_neighbors);
+        if let Some(chance) = &self.chance {
+            if healthy
+                && next == state
+                && infected_neighbors > 0
+                && chance.catches(infected_neighbors, generation, position)
+            {
+                next = CellState::Infected;
+            }
+        }
+        if state == CellState::Vaccinated
+            && next == CellState::Infected
+            && self.vaccine.resists(generation, position)
+        {
+            next = CellState::Vaccinated;
+        }
+        let caught = healthy && next == CellState::Infected;
+        if state == CellState::Infected
+            && next == CellState::Infected
+            && self.spread.kills(strain.lethality, generation, position)
+        {
+            next = CellState::Dead;
+        }
+        // a living cell that catches the infection incubates it before passing it on
+        if healthy && next == CellState::Infected && self.options.incubation > 0 {
+            next = CellState::Exposed;
+        }
+        if next == CellState::Alive && self.vaccine.vaccinates(generation, position) {
+            next = CellState::Vaccinated;
+        }
+        // only count the generations the options need, every other timer stays at zero,
+        // exposed cells always move on so any painted without an incubation are infected
+        let counted = match next {
+            CellState::Exposed => true,
+            CellState::Infected => self.options.recovery > 0,
+            CellState::Recovered => matches!(self.options.immunity, Immunity::Waning(_)),
+            _ => false,
+        };
+        let (next, timer) = if counted {
+            let timer = if next == state {
+                timer.saturating_add(1)
+            } else {
+                0
+            };
+            match (next, self.options.immunity) {
+                (CellState::Exposed, _) if timer >= self.options.incubation => {
+                    (CellState::Infected, 0)
+                }
+                (CellState::Infected, _) if timer >= self.options.recovery => {
+                    (CellState::Recovered, 0)
+                }
+                (CellState::Recovered, Immunity::Waning(generations)) if timer >= generations => {
+                    (CellState::Alive, 0)
+                }
+                _ => (next, timer),
+            }
+        } else {
+            (next, 0)
+        };
+        // only cells carrying the infection have a strain
+        let strain = match next {
+            CellState::Infected | CellState::Exposed
+                if caught && self.spread.mutates(generation, position) =>
+            {
+                carried | MUTATED
+            }
+            CellState::Infected | CellState::Exposed => carried,
+            _ => 0,
+        };
+        Cell {
+            state: next,
+            timer,
+            strain,
+        }
+    }
+}
+
+impl RuleTable {
+    // the birth and survival counts of the rules, if no cell is ever infected without
+    // infected neighbours and lone dead cells stay dead, so the game plays like plain life
+    pub fn life_rule(&self) -> Option<LifeRule> {
+        // a campaign vaccinates living cells, so they never stay plainly alive
+        if self.counts != 9
+            || self.get(CellState::Dead, 0, 0) != CellState::Dead
+            || self.vaccine.rate > 0
+        {
+            return None;
+        }
+        let mut rule = LifeRule {
+            birth: [false; 9],
+            survival: [false; 9],
+        };
+        for live_neighbors in 0..9 {
+            for (state, outcome) in [
+                (CellState::Alive, &mut rule.survival),
+                (CellState::Dead, &mut rule.birth),
+            ] {
+                match self.get(state, live_neighbors as u8, 0) {
+                    CellState::Alive => outcome[live_neighbors] = true,
+                    CellState::Dead => (),
+                    CellState::Infected
+                    | CellState::Recovered
+                    | CellState::Exposed
+                    | CellState::Vaccinated
+                    | CellState::Barrier => return None,
+                }
+            }
+        }
+        Some(rule)
+    }
+}
+
+impl Default for RuleTable {
+    fn default() -> Self {
+        Self::new(&Options::default())
+    }
+}
+
+// count the neighbours of the 64 cells in word k of the middle row, one bit of the
+// count per plane, from the eight words shifted onto them by adding a bit at a time,
+// the cells counted are picked out of each row's living and infected words by plane
+fn neighbour_counts(
+    rows: [Planes<&[u64]>; 3],
+    k: usize,
+    plane: impl Fn(u64, u64) -> u64,
+) -> [u64; 4] {
+    // the words of a row shifted so each cell lines up with its neighbours to the west and east
+    let shifted = |len: usize, word: &dyn Fn(usize) -> u64| {
+        let prev = if k > 0 { word(k - 1) } else { 0 };
+        let next = if k + 1 < len { word(k + 1) } else { 0 };
+        [(word(k) << 1) | (prev >> 63), (word(k) >> 1) | (next << 63)]
+    };
+    // a diagonal neighbour is hidden by barriers on both cells beside the corner between them
+    let barrier =
+        |(alive, infected, extra): Planes<&[u64]>, k: usize| extra[k] & !alive[k] & !infected[k];
+    let beside = shifted(rows[1].0.len(), &|k| barrier(rows[1], k));
+    let mut counts = [0; 4];
+    for (r, &(alive, infected, extra)) in rows.iter().enumerate() {
+        let word = |k: usize| plane(alive[k], infected[k]);
+        let [west, east] = shifted(alive.len(), &word);
+        let in_line = barrier((alive, infected, extra), k);
+        // the neighbours to the west, east and, outside the middle row, in line with each cell
+        let neighbours = if r == 1 {
+            [west, east, word(k)]
+        } else {
+            [
+                west & !(beside[0] & in_line),
+                east & !(beside[1] & in_line),
+                word(k),
+            ]
+        };
+        let shifts = if r == 1 { 2 } else { 3 };
+        for mut carry in neighbours.into_iter().take(shifts) {
+            for count in counts.iter_mut() {
+                (*count, carry) = (*count ^ carry, *count & carry);
+            }
+        }
+    }
+    counts
+}
+
+// the cells on the line from the centre of a cell to the centre of a neighbour, a barrier on
+// any of them hides the neighbour, as do barriers on both cells beside a corner it runs through
+#[derive(Clone, Debug, Default)]
+pub struct Sight {
+    cells: Vec<(isize, isize)>,
+    corners: Vec<[(isize, isize); 2]>,
+}
+
+impl Sight {
+    pub fn new((dx, dy): (isize, isize)) -> Self {
+        let mut sight = Self::default();
+        // the part of the line inside a cell along one axis, working in half cells
+        // so the edges of the cells fall on odd numbers
+        let inside = |c: isize, d: isize| {
+            if d == 0 {
+                if c == 0 {
+                    (0., 1.)
+                } else {
+                    (1., 0.)
+                }
+            } else {
+                let (a, b) = (
+                    (2 * c - 1) as f64 / (2 * d) as f64,
+                    (2 * c + 1) as f64 / (2 * d) as f64,
+                );
+                (a.min(b), a.max(b))
+            }
+        };
+        for y in dy.min(0)..=dy.max(0) {
+            for x in dx.min(0)..=dx.max(0) {
+                if (x, y) == (0, 0) || (x, y) == (dx, dy) {
+                    continue;
+                }
+                let ((x0, x1), (y0, y1)) = (inside(x, dx), inside(y, dy));
+                if x0.max(y0).max(0.) < x1.min(y1).min(1.) {
+                    sight.cells.push((x, y));
+                }
+            }
+        }
+        // the points on the line that land on whole half cells, corners land on odd ones
+        let steps = gcd(dx.unsigned_abs(), dy.unsigned_abs()) as isize;
+        for k in 1..2 * steps {
+            let (x, y) = (k * dx / steps, k * dy / steps);
+            if x % 2 != 0 && y % 2 != 0 {
+                let (sx, sy) = (dx.signum(), dy.signum());
+                sight
+                    .corners
+                    .push([((x + sx) / 2, (y - sy) / 2), ((x - sx) / 2, (y + sy) / 2)]);
+            }
+        }
+        sight
+    }
+
+    // whether nothing can come between the cell and the neighbour
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty() && self.corners.is_empty()
+    }
+
+    // whether the neighbour is hidden, given which cells around the cell are barriers
+    pub fn blocked(&self, barrier: impl Fn(isize, isize) -> bool) -> bool {
+        self.cells.iter().any(|&(x, y)| barrier(x, y))
+            || self
+                .corners
+                .iter()
+                .any(|pair| pair.iter().all(|&(x, y)| barrier(x, y)))
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// map a coordinate that may lie beyond the edges of the grid back onto it
+pub fn wrap(
+    boundary: Boundary,
+    x: isize,
+    y: isize,
+    width: usize,
+    height: usize,
+) -> Option<(usize, usize)> {
+    let (w, h) = (width as isize, height as isize);
+    match boundary {
+        // the world behind an unbounded grid is stepped on its own, so only the grid is covered here
+        Boundary::Dead | Boundary::Unbounded => {
+            if x < 0 || y < 0 || x >= w || y >= h {
+                None
+            } else {
+                Some((x as usize, y as usize))
+            }
+        }
+        Boundary::Torus => Some((x.rem_euclid(w) as usize, y.rem_euclid(h) as usize)),
+        Boundary::Mirror => {
+            // reflect about the edge, so the cell beyond an edge is the one on it
+            let reflect = |i: isize, n: isize| {
+                let i = i.rem_euclid(2 * n);
+                if i >= n {
+                    2 * n - 1 - i
+                } else {
+                    i
+                }
+            };
+            Some((reflect(x, w) as usize, reflect(y, h) as usize))
+        }
+        Boundary::KleinBottle => {
+            // every time the top or bottom is crossed the sides swap over
+            let x = x.rem_euclid(w);
+            let x = if y.div_euclid(h) % 2 != 0 {
+                w - 1 - x
+            } else {
+                x
+            };
+            Some((x as usize, y.rem_euclid(h) as usize))
+        }
+    }
+}
+
+// map a coordinate that may lie beyond the edges of a hex grid back onto it, working in doubled
+// columns where each cell sits at twice its column, plus one on odd rows, so the rows keep
+// their offsets across the edges however many rows there are
+pub fn wrap_hex(
+    boundary: Boundary,
+    x: isize,
+    y: isize,
+    width: usize,
+    height: usize,
+) -> Option<(usize, usize)> {
+    let (w, h) = (width as isize, height as isize);
+    let column = 2 * x + y.rem_euclid(2);
+    let (column, y) = match boundary {
+        Boundary::Dead | Boundary::Unbounded => return wrap(boundary, x, y, width, height),
+        Boundary::Torus => {
+            // crossing the top or bottom of an odd number of rows moves half a cell sideways
+            let crossings = y.div_euclid(h);
+            (
+                (column - crossings * (h % 2)).rem_euclid(2 * w),
+                y.rem_euclid(h),
+            )
+        }
+        Boundary::KleinBottle => {
+            // the sides swap over about the middle of the grid, which lands between
+            // columns when the row changes from even to odd
+            let column = if y.div_euclid(h) % 2 != 0 {
+                2 * w - 2 + h % 2 - column
+            } else {
+                column
+            };
+            (column.rem_euclid(2 * w), y.rem_euclid(h))
+        }
+        Boundary::Mirror => {
+            // rows only line up again when reflected about a line through cells, so the
+            // sides reflect about the half cells sticking out past every other row and the
+            // top and bottom about the rows just beyond them, the cells on those lines
+            // reflect onto themselves and count as dead
+            let reflect = |i: isize, low: isize, high: isize| {
+                let i = (i - low).rem_euclid(2 * (high - low)) + low;
+                if i > high {
+                    2 * high - i
+                } else {
+                    i
+                }
+            };
+            (reflect(column, -1, 2 * w), reflect(y, -1, h))
+        }
+    };
+    if !(0..2 * w).contains(&column) || !(0..h).contains(&y) {
+        return None;
+    }
+    Some((((column - y % 2) / 2) as usize, y as usize))
+}
+
+// a plane that fills with life at once can't be stored, so it keeps dead edges instead
+fn with_storable_boundary(mut options: Options) -> Options {
+    if options.boundary == Boundary::Unbounded && options.fills_the_plane() {
+        options.boundary = Boundary::Dead;
+    }
+    options
+}
+
+pub fn cell_check(
+    current_state: CellState,
+    live_neighbors: u8,
+    infected_neighbors: u8,
+    options: &Options,
+) -> CellState {
+    // the number of infected neighbours needed to infect a cell shrinks as virulence grows
+    let max_neighbors = options.max_neighbours();
+    // infection left to chance is rolled for while stepping, so only a threshold infects here
+    let infected_enough = |threshold: u8| {
+        options.infection == Infection::Threshold && infected_neighbors >= threshold
+    };
+    // check if the default options are in use
+    if options == &Options::default() {
+        // check the cell against the criteria
+        match current_state {
+            CellState::Alive => {
+                if live_neighbors < 2 {
+                    CellState::Dead
+                } else if live_neighbors > 3
+                    || infected_enough(max_neighbors.saturating_sub(options.virulence))
+                {
+                    CellState::Infected
+                } else {
+                    CellState::Alive
+                }
+            }
+            CellState::Dead => {
+                if live_neighbors == 3 {
+                    CellState::Alive
+                } else {
+                    CellState::Dead
+                }
+            }
+            CellState::Infected => {
+                if live_neighbors < 3 {
+                    CellState::Dead
+                } else {
+                    CellState::Infected
+                }
+            }
+            CellState::Recovered | CellState::Exposed => {
+                immune_check(current_state, live_neighbors, options)
+            }
+            CellState::Vaccinated => vaccinated_check(live_neighbors, infected_neighbors, options),
+            CellState::Barrier => CellState::Barrier,
+        }
+    } else {
+        // check the cell against the user defined criteria
+        match current_state {
+            CellState::Alive => match options.living_rule {
+                Rules::Single(i) => {
+                    if live_neighbors < i {
+                        CellState::Dead
+                    } else if live_neighbors == i {
+                        CellState::Alive
+                    } else if live_neighbors > i + 1
+                        || infected_enough(max_neighbors.saturating_sub(options.virulence))
+                    {
+                        CellState::Infected
+                    } else {
+                        CellState::Alive
+                    }
+                }
+                Rules::Range { min: _, max: u } => {
+                    if !options
+                        .living_rule
+                        .in_range(RuleKind::Living, live_neighbors)
+                    {
+                        CellState::Dead
+                    } else if live_neighbors > u + 1
+                        || infected_enough(max_neighbors.saturating_sub(options.virulence))
+                    {
+                        CellState::Infected
+                    } else {
+                        CellState::Alive
+                    }
+                }
+                Rules::Singles(_) => {
+                    if !options
+                        .living_rule
+                        .in_range(RuleKind::Living, live_neighbors)
+                    {
+                        CellState::Dead
+                    } else if options
+                        .living_rule
+                        .max(RuleKind::Living)
+                        .is_some_and(|max| live_neighbors > max)
+                        || infected_enough(max_neighbors.saturating_sub(options.virulence))
+                    {
+                        CellState::Infected
+                    } else {
+                        CellState::Alive
+                    }
+                }
+                _ => {
+                    if live_neighbors < 2 || (options.virulence == 0 && live_neighbors > 3) {
+                        CellState::Dead
+                    } else if live_neighbors > 3
+                        || infected_enough(max_neighbors.saturating_sub(options.virulence))
+                    {
+                        CellState::Infected
+                    } else {
+                        CellState::Alive
+                    }
+                }
+            },
+            CellState::Dead => match options.dead_rule {
+                Rules::Single(i) => {
+                    if live_neighbors == i {
+                        CellState::Alive
+                    } else {
+                        CellState::Dead
+                    }
+                }
+                Rules::Range { min: _, max: _ } => {
+                    if options.dead_rule.in_range(RuleKind::Dead, live_neighbors) {
+                        CellState::Alive
+                    } else {
+                        CellState::Dead
+                    }
+                }
+                Rules::Singles(_) => {
+                    if options.dead_rule.in_range(RuleKind::Dead, live_neighbors) {
+                        CellState::Alive
+                    } else {
+                        CellState::Dead
+                    }
+                }
+                _ => {
+                    if live_neighbors == 3 {
+                        CellState::Alive
+                    } else {
+                        CellState::Dead
+                    }
+                }
+            },
+            CellState::Infected => {
+                if live_neighbors <= options.virulence {
+                    CellState::Dead
+                } else {
+                    CellState::Infected
+                }
+            }
+            CellState::Recovered | CellState::Exposed => {
+                immune_check(current_state, live_neighbors, options)
+            }
+            CellState::Vaccinated => vaccinated_check(live_neighbors, infected_neighbors, options),
+            CellState::Barrier => CellState::Barrier,
+        }
+    }
+}
+
+// recovered cells, and exposed cells that already carry the infection,
+// live and die like healthy ones but can't catch it again
+fn immune_check(current_state: CellState, live_neighbors: u8, options: &Options) -> CellState {
+    match cell_check(CellState::Alive, live_neighbors, 0, options) {
+        CellState::Dead => CellState::Dead,
+        _ => current_state,
+    }
+}
+
+// vaccinated cells live, die and catch the infection like healthy ones,
+// though the vaccine might still hold off the infection when it's rolled for
+fn vaccinated_check(live_neighbors: u8, infected_neighbors: u8, options: &Options) -> CellState {
+    match cell_check(
+        CellState::Alive,
+        live_neighbors,
+        infected_neighbors,
+        options,
+    ) {
+        CellState::Alive => CellState::Vaccinated,
+        next => next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{edits::EditHistory, fixtures::scattered};
+
+    // options that reduce the game to conway's game of life
+    fn life() -> Options {
+        Options {
+            virulence: 0,
+            ..Options::default()
+        }
+    }
+
+    fn simulation(alive: &[(usize, usize)]) -> Simulation {
+        let mut cells = CellStates::new(30, 30);
+        for &(x, y) in alive {
+            cells.set(x, y, CellState::Alive);
+        }
+        Simulation::new(cells, life())
+    }
+
+    fn alive(simulation: &Simulation) -> Vec<(usize, usize)> {
+        let mut alive = Vec::new();
+        for x in 0..simulation.cells.width() {
+            for y in 0..simulation.cells.height() {
+                if simulation.cells.get(x, y) == CellState::Alive {
+                    alive.push((x, y));
+                }
+            }
+        }
+        alive
+    }
+
+    fn shifted(cells: &[(usize, usize)], dx: usize, dy: usize) -> Vec<(usize, usize)> {
+        let mut cells: Vec<_> = cells.iter().map(|&(x, y)| (x + dx, y + dy)).collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn step_counts_generations() {
+        let mut simulation = simulation(&[]);
+        simulation.step();
+        simulation.step();
+        assert_eq!(simulation.generation, 2);
+    }
+
+    #[test]
+    fn resize_keeps_the_pattern_that_fits() {
+        let mut simulation = simulation(&[(1, 1), (20, 2), (2, 20)]);
+        simulation.resize(10, 25);
+        assert_eq!(simulation.cells.size(), (10, 25));
+        assert_eq!(alive(&simulation), [(1, 1), (2, 20)]);
+        simulation.step();
+        assert_eq!(simulation.cells.size(), (10, 25));
+    }
+
+    #[test]
+    fn boundaries_map_coordinates_onto_the_grid() {
+        assert_eq!(wrap(Boundary::Dead, -1, 0, 10, 5), None);
+        assert_eq!(wrap(Boundary::Dead, 9, 4, 10, 5), Some((9, 4)));
+        assert_eq!(wrap(Boundary::Torus, -1, 5, 10, 5), Some((9, 0)));
+        assert_eq!(wrap(Boundary::Mirror, -1, 5, 10, 5), Some((0, 4)));
+        assert_eq!(wrap(Boundary::Mirror, 10, -2, 10, 5), Some((9, 1)));
+        assert_eq!(wrap(Boundary::KleinBottle, -1, 2, 10, 5), Some((9, 2)));
+        assert_eq!(wrap(Boundary::KleinBottle, 2, -1, 10, 5), Some((7, 4)));
+        assert_eq!(wrap(Boundary::KleinBottle, 2, 5, 10, 5), Some((7, 0)));
+        // hex rows keep their offsets across the edges
+        assert_eq!(wrap_hex(Boundary::Torus, 3, -1, 10, 5), Some((4, 4)));
+        assert_eq!(wrap_hex(Boundary::Torus, 3, -1, 10, 4), Some((3, 3)));
+        assert_eq!(wrap_hex(Boundary::KleinBottle, 2, -1, 10, 5), Some((7, 4)));
+        assert_eq!(wrap_hex(Boundary::Mirror, -1, 0, 10, 5), Some((0, 0)));
+        assert_eq!(wrap_hex(Boundary::Mirror, -1, 1, 10, 5), None);
+        assert_eq!(wrap_hex(Boundary::Mirror, 3, -1, 10, 5), None);
+    }
+
+    #[test]
+    fn glider_wraps_around_a_torus() {
+        let glider = [(11, 10), (12, 11), (10, 12), (11, 12), (12, 12)];
+        let mut simulation = simulation(&glider);
+        simulation.options.boundary = Boundary::Torus;
+        // a glider crosses the 30x30 grid and returns after 120 generations
+        for _ in 0..120 {
+            simulation.step();
+        }
+        assert_eq!(alive(&simulation), shifted(&glider, 0, 0));
+    }
+
+    #[test]
+    fn glider_dies_against_a_dead_edge() {
+        let glider = [(11, 10), (12, 11), (10, 12), (11, 12), (12, 12)];
+        let mut simulation = simulation(&glider);
+        for _ in 0..120 {
+            simulation.step();
+        }
+        assert_ne!(alive(&simulation), shifted(&glider, 0, 0));
+    }
+
+    #[test]
+    fn blinker_across_a_torus_edge_oscillates() {
+        let horizontal = [(0, 10), (1, 10), (29, 10)];
+        let mut simulation = simulation(&horizontal);
+        simulation.options.boundary = Boundary::Torus;
+        simulation.step();
+        assert_eq!(alive(&simulation), [(0, 9), (0, 10), (0, 11)]);
+        simulation.step();
+        assert_eq!(alive(&simulation), horizontal);
+    }
+
+    #[test]
+    fn von_neumann_ignores_diagonals() {
+        let mut simulation = simulation(&[(9, 9), (11, 11), (10, 11)]);
+        simulation.options.neighbourhood = Neighbourhood::VonNeumann(1);
+        assert_eq!(simulation.count_neighbors(10, 10), (1, 0));
+        simulation.options.neighbourhood = Neighbourhood::Moore(1);
+        assert_eq!(simulation.count_neighbors(10, 10), (3, 0));
+    }
+
+    #[test]
+    fn virulence_scales_with_the_neighbourhood() {
+        let mut options = life();
+        options.virulence = 1;
+        options.living_rule = Rules::Range { min: 0, max: 24 };
+        options.neighbourhood = Neighbourhood::VonNeumann(1);
+        // 4 neighbours minus a virulence of 1 leaves 3 infected neighbours needed
+        assert_eq!(
+            cell_check(CellState::Alive, 0, 3, &options),
+            CellState::Infected
+        );
+        assert_eq!(
+            cell_check(CellState::Alive, 0, 2, &options),
+            CellState::Alive
+        );
+        options.neighbourhood = Neighbourhood::Moore(2);
+        assert_eq!(
+            cell_check(CellState::Alive, 0, 3, &options),
+            CellState::Alive
+        );
+        assert_eq!(
+            cell_check(CellState::Alive, 0, 23, &options),
+            CellState::Infected
+        );
+        // the default survival rule takes the threshold from the virulence too
+        let options = Options {
+            virulence: 5,
+            ..Options::default()
+        };
+        assert_eq!(
+            cell_check(CellState::Alive, 2, 3, &options),
+            CellState::Infected
+        );
+        assert_eq!(
+            cell_check(CellState::Alive, 2, 2, &options),
+            CellState::Alive
+        );
+    }
+
+    #[test]
+    fn hex_cells_have_six_neighbours() {
+        let mut simulation = simulation(&[]);
+        simulation.options.layout = GridLayout::Hex;
+        for y in 0..3 {
+            for x in 0..3 {
+                simulation.cells.set(x + 4, y + 4, CellState::Alive);
+            }
+        }
+        // an even row touches the rows above and below to its left, an odd row to its right
+        assert_eq!(simulation.count_neighbors(5, 4), (4, 0));
+        assert_eq!(simulation.count_neighbors(6, 5), (3, 0));
+        assert_eq!(simulation.count_neighbors(5, 5), (6, 0));
+        assert_eq!(simulation.count_neighbors(4, 6), (2, 0));
+        assert_eq!(simulation.options.max_neighbours(), 6);
+    }
+
+    #[test]
+    fn hex_neighbours_stay_symmetric_across_the_edges() {
+        for (boundary, height) in [Boundary::Torus, Boundary::KleinBottle, Boundary::Mirror]
+            .into_iter()
+            .flat_map(|boundary| [(boundary, 4), (boundary, 5)])
+        {
+            let options = Options {
+                layout: GridLayout::Hex,
+                boundary,
+                ..Options::default()
+            };
+            // how often each cell counts each other cell as a neighbour,
+            // which should be the same both ways round
+            let width = 6;
+            let counts: Vec<Vec<u8>> = (0..width * height)
+                .map(|a| {
+                    let mut simulation =
+                        Simulation::new(CellStates::new(width, height), options.clone());
+                    simulation.cells.set(a % width, a / width, CellState::Alive);
+                    (0..width * height)
+                        .map(|b| simulation.count_neighbors(b % width, b / width).0)
+                        .collect()
+                })
+                .collect();
+            for (a, row) in counts.iter().enumerate() {
+                for (b, &count) in row.iter().enumerate() {
+                    assert_eq!(count, counts[b][a], "{:?} {}", boundary, height);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hex_neighbours_are_symmetric() {
+        for (offsets, y) in [(HEX_EVEN_ROW, 4), (HEX_ODD_ROW, 5)] {
+            for (dx, dy) in offsets {
+                // step back from the neighbour and land on the original cell
+                let ny = y + dy;
+                let back = if ny % 2 == 0 {
+                    HEX_EVEN_ROW
+                } else {
+                    HEX_ODD_ROW
+                };
+                assert!(back.contains(&(-dx, -dy)));
+            }
+        }
+    }
+
+    #[test]
+    fn empty_rules_never_match() {
+        let options = Options {
+            living_rule: Rules::Singles(vec![]),
+            dead_rule: Rules::Singles(vec![]),
+            ..life()
+        };
+        assert_eq!(
+            cell_check(CellState::Alive, 2, 0, &options),
+            CellState::Dead
+        );
+        assert_eq!(cell_check(CellState::Dead, 3, 0, &options), CellState::Dead);
+    }
+
+    // step the grid by checking every cell against the options directly
+    fn step_uncompiled(simulation: &mut Simulation) {
+        let mut next = simulation.cells.clone();
+        let (even_row, odd_row) = simulation.offsets();
+        let walled = simulation.cells.has_barriers();
+        for y in 0..next.height() {
+            let offsets = if y.is_multiple_of(2) {
+                &even_row
+            } else {
+                &odd_row
+            };
+            for x in 0..next.width() {
+                let (live_neighbors, infected_neighbors) = simulation.count(x, y, offsets, walled);
+                next.set(
+                    x,
+                    y,
+                    cell_check(
+                        simulation.cells.get(x, y),
+                        live_neighbors,
+                        infected_neighbors,
+                        &simulation.options,
+                    ),
+                );
+            }
+        }
+        simulation.cells = next;
+    }
+
+    #[test]
+    fn rule_table_matches_cell_check() {
+        for options in [
+            Options::default(),
+            life(),
+            Options {
+                living_rule: Rules::Singles(vec![1, 3, 4]),
+                dead_rule: Rules::Range { min: 2, max: 3 },
+                virulence: 5,
+                ..Options::default()
+            },
+            Options {
+                living_rule: Rules::Single(3),
+                neighbourhood: Neighbourhood::Moore(2),
+                boundary: Boundary::Torus,
+                ..Options::default()
+            },
+        ] {
+            let mut compiled = Simulation::new(scattered(40, 30), options.clone());
+            let mut uncompiled = Simulation::new(scattered(40, 30), options);
+            for _ in 0..5 {
+                compiled.step();
+                step_uncompiled(&mut uncompiled);
+                assert_eq!(compiled.cells, uncompiled.cells);
+            }
+        }
+    }
+
+    #[test]
+    fn rule_table_follows_changed_options() {
+        let mut simulation = simulation(&[(9, 10), (10, 10), (11, 10)]);
+        simulation.step();
+        simulation.options.dead_rule = Rules::Singles(vec![]);
+        simulation.step();
+        assert_eq!(alive(&simulation), [(10, 10)]);
+    }
+
+    // run with: cargo test --release -- --ignored --nocapture bench
+    #[test]
+    #[ignore]
+    fn bench_rule_table() {
+        use std::time::Instant;
+        for options in [
+            Options::default(),
+            Options {
+                living_rule: Rules::Singles(vec![2, 3, 5]),
+                neighbourhood: Neighbourhood::Custom(vec![(-1, 0), (1, 0), (0, -1), (0, 1)]),
+                ..Options::default()
+            },
+        ] {
+            let mut simulation = Simulation::new(scattered(1000, 1000), options);
+            simulation.threads = 1;
+            let start = Instant::now();
+            for _ in 0..5 {
+                step_uncompiled(&mut simulation);
+            }
+            let uncompiled = start.elapsed() / 5;
+            let start = Instant::now();
+            for _ in 0..5 {
+                simulation.step();
+            }
+            let compiled = start.elapsed() / 5;
+            println!(
+                "1000x1000 step: {:?} checking every cell, {:?} with the rule table ({:.1}x)",
+                uncompiled,
+                compiled,
+                uncompiled.as_secs_f64() / compiled.as_secs_f64()
+            );
+        }
+    }
+
+    #[test]
+    fn packed_cells_keep_their_states() {
+        let cells = scattered(130, 7);
+        let states: Vec<Cell> = cells
+            .states()
+            .map(|state| Cell {
+                state,
+                ..Cell::default()
+            })
+            .collect();
+        let mut copy = CellStates::new(130, 7);
+        copy.set_cells(&states);
+        assert_eq!(copy, cells);
+        assert_eq!(cells.resized(65, 3).get(64, 2), cells.get(64, 2));
+        let mut every = CellStates::new(CellState::ALL.len(), 1);
+        for (x, &state) in CellState::ALL.iter().enumerate() {
+            every.set(x, 0, state);
+            assert_eq!(every.get(x, 0), state);
+        }
+        // the padding after the last cell of each row stays clear
+        for y in 0..7 {
+            let (alive, infected, extra) = cells.row_words(y);
+            assert_eq!((alive[2] | infected[2] | extra[2]) >> 2, 0);
+        }
+    }
+
+    #[test]
+    fn timers_and_strains_are_only_stored_when_needed() {
+        let empty = CellStates::new(40, 40).bytes();
+        // hex grids are stepped a cell at a time, so every cell is written
+        let options = Options {
+            layout: GridLayout::Hex,
+            ..Options::default()
+        };
+        let mut simulation = Simulation::new(scattered(40, 40), options);
+        simulation.step();
+        assert_eq!(simulation.cells.bytes(), empty);
+        simulation.options.recovery = 3;
+        simulation.step();
+        assert!(simulation.cells.bytes() > empty);
+        simulation.options.recovery = 0;
+        simulation.step();
+        assert_eq!(simulation.cells.bytes(), empty);
+        // timers that went back to zero are the same as timers that were never stored
+        let mut stored = CellStates::new(4, 4);
+        stored.set_timer(1, 1, 5);
+        stored.set_timer(1, 1, 0);
+        assert_eq!(stored, CellStates::new(4, 4));
+        let hash = |cells: &CellStates| {
+            let mut hasher = DefaultHasher::new();
+            cells.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&stored), hash(&CellStates::new(4, 4)));
+    }
+
+    #[test]
+    fn packed_step_matches_cell_step() {
+        for options in [
+            Options::default(),
+            life(),
+            Options {
+                living_rule: Rules::Singles(vec![1, 3, 4]),
+                dead_rule: Rules::Range { min: 2, max: 3 },
+                virulence: 5,
+                ..Options::default()
+            },
+            // dead cells with no neighbours come alive, so no word can be skipped
+            Options {
+                dead_rule: Rules::Singles(vec![0, 3]),
+                ..Options::default()
+            },
+        ] {
+            for (width, height) in [(150, 40), (128, 20), (1, 9), (63, 1)] {
+                let mut packed = Simulation::new(scattered(width, height), options.clone());
+                assert!(packed.packed());
+                let mut cells = Simulation::new(scattered(width, height), options.clone());
+                for _ in 0..5 {
+                    packed.step();
+                    cells.step_cells(1);
+                    assert_eq!(packed.cells, cells.cells);
+                }
+            }
+        }
+    }
+
+    fn chance(probability: f32, seed: u64) -> Options {
+        Options {
+            infection: Infection::Chance { probability, seed },
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn chance_infection_is_reproducible() {
+        let mut packed = Simulation::new(scattered(100, 60), chance(0.3, 5));
+        let mut cells = Simulation::new(scattered(100, 60), chance(0.3, 5));
+        let mut reseeded = Simulation::new(scattered(100, 60), chance(0.3, 6));
+        for _ in 0..5 {
+            packed.step();
+            cells.step_cells(1);
+            // rolls depend on the generation, which only step moves on
+            cells.generation += 1;
+            reseeded.step();
+            assert_eq!(packed.cells, cells.cells);
+        }
+        assert_ne!(packed.cells, reseeded.cells);
+    }
+
+    #[test]
+    fn chance_sets_how_often_infection_spreads() {
+        // a living cell kept alive by two neighbours, next to one infected cell
+        let mut cells = CellStates::new(10, 10);
+        for (x, y) in [(4, 5), (5, 5), (6, 5)] {
+            cells.set(x, y, CellState::Alive);
+        }
+        cells.set(5, 6, CellState::Infected);
+        let caught = |probability: f32, seed: u64| {
+            let mut simulation = Simulation::new(cells.clone(), chance(probability, seed));
+            simulation.step();
+            simulation.cells.get(5, 5) == CellState::Infected
+        };
+        assert!((0..100).all(|seed| caught(1., seed)));
+        assert!((0..100).all(|seed| !caught(0., seed)));
+        let infected = (0..2000).filter(|&seed| caught(0.3, seed)).count();
+        assert!(
+            (500..700).contains(&infected),
+            "{} of 2000 infected",
+            infected
+        );
+    }
+
+    // every fourth cell of the scattered grid recovered instead, every seventh
+    // exposed, every eleventh vaccinated and every thirteenth a barrier
+    fn scattered_timed(width: usize, height: usize) -> CellStates {
+        let mut cells = scattered(width, height);
+        for i in (0..width * height).step_by(4) {
+            cells.set(i % width, i / width, CellState::Recovered);
+        }
+        for i in (0..width * height).step_by(7) {
+            cells.set(i % width, i / width, CellState::Exposed);
+        }
+        for i in (0..width * height).step_by(11) {
+            cells.set(i % width, i / width, CellState::Vaccinated);
+        }
+        for i in (0..width * height).step_by(13) {
+            cells.set(i % width, i / width, CellState::Barrier);
+        }
+        cells
+    }
+
+    #[test]
+    fn recovered_cells_are_immune_and_count_as_living() {
+        // a cell kept alive by two neighbours, next to an infected cell that always infects it
+        let caught = |state: CellState| {
+            let mut cells = CellStates::new(10, 10);
+            cells.set(4, 5, CellState::Alive);
+            cells.set(5, 5, state);
+            cells.set(6, 5, CellState::Alive);
+            cells.set(5, 6, CellState::Infected);
+            let mut simulation = Simulation::new(cells, chance(1., 0));
+            simulation.step();
+            simulation.cells.get(5, 5)
+        };
+        assert_eq!(caught(CellState::Alive), CellState::Infected);
+        assert_eq!(caught(CellState::Recovered), CellState::Recovered);
+        // a blinker with a recovered cell in it still turns over
+        let mut blinker = simulation(&[(9, 10), (11, 10)]);
+        blinker.set(10, 10, CellState::Recovered);
+        blinker.step();
+        assert_eq!(alive(&blinker), [(10, 9), (10, 11)]);
+        assert_eq!(blinker.cells.get(10, 10), CellState::Recovered);
+    }
+
+    #[test]
+    fn infected_cells_recover_and_immunity_wanes() {
+        // a block with an infected corner, kept alive by the rest of the block
+        let mut simulation = simulation(&[(6, 5), (5, 6), (6, 6)]);
+        simulation.options.recovery = 3;
+        simulation.options.immunity = Immunity::Waning(2);
+        simulation.set(5, 5, CellState::Infected);
+        let history: Vec<CellState> = (0..7)
+            .map(|_| {
+                simulation.step();
+                simulation.cells.get(5, 5)
+            })
+            .collect();
+        assert_eq!(
+            history,
+            [
+                CellState::Infected,
+                CellState::Infected,
+                CellState::Recovered,
+                CellState::Recovered,
+                CellState::Alive,
+                CellState::Alive,
+                CellState::Alive,
+            ]
+        );
+        // with permanent immunity it stays recovered
+        simulation.options.immunity = Immunity::Permanent;
+        simulation.set(5, 5, CellState::Infected);
+        for _ in 0..10 {
+            simulation.step();
+        }
+        assert_eq!(simulation.cells.get(5, 5), CellState::Recovered);
+        assert_eq!(alive(&simulation), [(5, 6), (6, 5), (6, 6)]);
+    }
+
+    #[test]
+    fn timed_cells_step_the_same_on_every_path() {
+        for options in [
+            Options::default(),
+            Options {
+                recovery: 2,
+                ..Options::default()
+            },
+            Options {
+                recovery: 1,
+                immunity: Immunity::Waning(3),
+                ..Options::default()
+            },
+            Options {
+                incubation: 2,
+                recovery: 3,
+                ..chance(0.5, 4)
+            },
+            Options {
+                vaccination: Vaccination {
+                    efficacy: 0.5,
+                    rate: 0.1,
+                    start: 2,
+                    seed: 9,
+                },
+                ..chance(0.5, 4)
+            },
+        ] {
+            let mut packed = Simulation::new(scattered_timed(150, 40), options.clone());
+            assert_eq!(packed.packed(), !options.timed());
+            let mut cells = Simulation::new(scattered_timed(150, 40), options.clone());
+            let mut parallel = Simulation::new(scattered_timed(150, 40), options);
+            for _ in 0..5 {
+                packed.step();
+                cells.step_cells(1);
+                cells.generation += 1;
+                parallel.step_cells(4);
+                parallel.generation += 1;
+                assert_eq!(packed.cells, cells.cells);
+                assert_eq!(parallel.cells, cells.cells);
+            }
+        }
+    }
+
+    fn mutating(probability: f32, mutation: f32, seed: u64) -> Options {
+        Options {
+            pathogen: Pathogen {
+                lethality: 0.1,
+                mutation,
+                seed,
+            },
+            ..chance(probability, 3)
+        }
+    }
+
+    #[test]
+    fn strains_mutate_the_same_way_on_every_thread() {
+        let options = mutating(0.6, 0.05, 5);
+        let mut single = Simulation::new(scattered(200, 100), options.clone());
+        single.threads = 1;
+        let mut parallel = Simulation::new(scattered(200, 100), options);
+        parallel.threads = 4;
+        let mut reseeded = Simulation::new(scattered(200, 100), mutating(0.6, 0.05, 6));
+        assert!(!single.packed());
+        for _ in 0..3 {
+            single.step();
+            parallel.step();
+            reseeded.step();
+            assert_eq!(parallel.cells, single.cells);
+            assert_eq!(parallel.strains(), single.strains());
+        }
+        assert!(single.strains().count() > 1);
+        assert_ne!(reseeded.strains(), single.strains());
+        // every infected cell is counted under a strain that has arisen
+        let infected = single
+            .cells
+            .states()
+            .filter(|&state| state == CellState::Infected)
+            .count();
+        let counts = single.strain_counts();
+        assert_eq!(counts.len(), single.strains().count());
+        assert_eq!(counts.iter().sum::<usize>(), infected);
+        assert!(counts[1..].iter().any(|&count| count > 0));
+    }
+
+    #[test]
+    fn restored_games_replay_with_their_strains() {
+        let mut simulation = Simulation::new(scattered(200, 100), mutating(0.6, 0.05, 5));
+        simulation.step();
+        let start = simulation.snapshot();
+        simulation.step();
+        simulation.step();
+        let stepped = simulation.snapshot();
+        assert!(simulation.strains().count() > start.strains.count());
+        simulation.restore(&start);
+        assert_eq!(simulation.strains(), &start.strains);
+        simulation.step();
+        simulation.step();
+        assert_eq!(simulation.snapshot(), stepped);
+    }
+
+    #[test]
+    fn lethal_strains_kill_their_hosts() {
+        // blocks with an infected corner, kept alive by the other three for a generation
+        let blocks = || {
+            let mut cells = CellStates::new(120, 120);
+            for y in (0..120).step_by(3) {
+                for x in (0..120).step_by(3) {
+                    cells.set(x, y, CellState::Infected);
+                    for (dx, dy) in [(1, 0), (0, 1), (1, 1)] {
+                        cells.set(x + dx, y + dy, CellState::Alive);
+                    }
+                }
+            }
+            cells
+        };
+        let survivors = |lethality: f32| {
+            let options = Options {
+                pathogen: Pathogen {
+                    lethality,
+                    ..Pathogen::default()
+                },
+                ..Options::default()
+            };
+            let mut simulation = Simulation::new(blocks(), options);
+            simulation.step();
+            (0..120)
+                .step_by(3)
+                .flat_map(|y| (0..120).step_by(3).map(move |x| (x, y)))
+                .filter(|&(x, y)| simulation.cells.get(x, y) == CellState::Infected)
+                .count() as f32
+                / 1600.
+        };
+        assert_eq!(survivors(0.), 1.);
+        assert!((survivors(0.5) - 0.5).abs() < 0.05);
+        assert_eq!(survivors(1.), 0.);
+    }
+
+    #[test]
+    fn strains_keep_their_own_virulence() {
+        let mut strains = Strains::new(2, 0.);
+        let mut milder = 0;
+        for roll in 0.. {
+            milder = strains.mutate(0, roll, 8);
+            if strains.get(milder).virulence == 1 {
+                break;
+            }
+        }
+        // infected cells with two living neighbours, enough only for the milder strain
+        let mut cells = CellStates::new(10, 10);
+        for (x, strain) in [(2, 0), (6, milder)] {
+            cells.set(x, 5, CellState::Infected);
+            cells.set_strain(x, 5, strain);
+            cells.set(x - 1, 5, CellState::Alive);
+            cells.set(x + 1, 5, CellState::Alive);
+        }
+        let mut simulation = Simulation::new(cells, Options::default());
+        simulation.strains = strains;
+        assert!(simulation.strained());
+        simulation.step();
+        assert_eq!(simulation.cells.get(2, 5), CellState::Dead);
+        assert_eq!(simulation.cells.get(6, 5), CellState::Infected);
+        assert_eq!(simulation.cells.strain(6, 5), milder);
+        // clearing the grid forgets the strains
+        simulation.clear();
+        assert_eq!(simulation.strains().count(), 1);
+    }
+
+    #[test]
+    fn exposed_cells_incubate_before_infecting() {
+        // a block with an infected corner that always passes it on
+        let mut cells = CellStates::new(10, 10);
+        for (x, y) in [(6, 5), (5, 6), (6, 6)] {
+            cells.set(x, y, CellState::Alive);
+        }
+        cells.set(5, 5, CellState::Infected);
+        let options = Options {
+            incubation: 2,
+            recovery: 3,
+            ..chance(1., 0)
+        };
+        let mut simulation = Simulation::new(cells, options);
+        let history: Vec<(CellState, CellState)> = (0..4)
+            .map(|_| {
+                simulation.step();
+                (simulation.cells.get(5, 5), simulation.cells.get(6, 6))
+            })
+            .collect();
+        assert_eq!(
+            history,
+            [
+                (CellState::Infected, CellState::Exposed),
+                (CellState::Infected, CellState::Exposed),
+                (CellState::Recovered, CellState::Infected),
+                // the newly infected cells have too few living neighbours to survive
+                (CellState::Dead, CellState::Dead),
+            ]
+        );
+        // an exposed cell painted when the infection doesn't incubate is infected straight away
+        let mut cells = CellStates::new(10, 10);
+        cells.set(4, 5, CellState::Alive);
+        cells.set(5, 5, CellState::Exposed);
+        cells.set(6, 5, CellState::Alive);
+        let mut simulation = Simulation::new(cells, Options::default());
+        simulation.step();
+        assert_eq!(simulation.cells.get(5, 5), CellState::Infected);
+    }
+
+    fn vaccinated(efficacy: f32, seed: u64) -> Options {
+        Options {
+            vaccination: Vaccination {
+                efficacy,
+                seed,
+                ..Vaccination::default()
+            },
+            ..chance(1., seed)
+        }
+    }
+
+    #[test]
+    fn vaccines_hold_off_the_infection_as_often_as_they_work() {
+        // a vaccinated cell kept alive by two neighbours, next to an infected cell that always infects it
+        let mut cells = CellStates::new(10, 10);
+        cells.set(4, 5, CellState::Alive);
+        cells.set(5, 5, CellState::Vaccinated);
+        cells.set(6, 5, CellState::Alive);
+        cells.set(5, 6, CellState::Infected);
+        let caught = |efficacy: f32, seed: u64| {
+            let mut simulation = Simulation::new(cells.clone(), vaccinated(efficacy, seed));
+            simulation.step();
+            simulation.cells.get(5, 5) == CellState::Infected
+        };
+        assert!((0..100).all(|seed| !caught(1., seed)));
+        assert!((0..100).all(|seed| caught(0., seed)));
+        let infected = (0..2000).filter(|&seed| caught(0.7, seed)).count();
+        assert!(
+            (500..700).contains(&infected),
+            "{} of 2000 infected",
+            infected
+        );
+        // otherwise vaccinated cells live and die like healthy ones
+        let mut blinker = simulation(&[(9, 10), (11, 10)]);
+        blinker.set(10, 10, CellState::Vaccinated);
+        blinker.step();
+        assert_eq!(alive(&blinker), [(10, 9), (10, 11)]);
+        assert_eq!(blinker.cells.get(10, 10), CellState::Vaccinated);
+    }
+
+    #[test]
+    fn campaigns_vaccinate_living_cells_once_started() {
+        let block = [(5, 5), (5, 6), (6, 5), (6, 6)];
+        let mut game = simulation(&block);
+        game.options.vaccination = Vaccination {
+            rate: 1.,
+            start: 2,
+            ..Vaccination::default()
+        };
+        game.step();
+        game.step();
+        assert_eq!(alive(&game), block);
+        game.step();
+        assert!(alive(&game).is_empty());
+        assert!(block
+            .iter()
+            .all(|&(x, y)| game.cells.get(x, y) == CellState::Vaccinated));
+        // a campaign stops the game being skipped through as plain life
+        game.options.boundary = Boundary::Unbounded;
+        game.set_options(game.options.clone());
+        assert!(game.hashlife_rule().is_none());
+        // about the given share of cells are vaccinated each generation, out of 49 blocks
+        let mut field = simulation(&[]);
+        for x in 0..7 {
+            for y in 0..7 {
+                for (dx, dy) in block {
+                    field.set(x * 4 + dx - 5, y * 4 + dy - 5, CellState::Alive);
+                }
+            }
+        }
+        field.options.vaccination.rate = 0.25;
+        field.step();
+        let vaccinated = field
+            .cells
+            .states()
+            .filter(|&state| state == CellState::Vaccinated)
+            .count();
+        assert!((25..=75).contains(&vaccinated), "{} vaccinated", vaccinated);
+    }
+
+    #[test]
+    fn the_brush_vaccinates_living_cells_around_the_cursor() {
+        let mut simulation = simulation(&[(0, 0), (1, 1), (2, 2), (3, 3), (5, 5)]);
+        simulation.set(1, 2, CellState::Infected);
+        let before = simulation.cells.clone();
+        let mut history = EditHistory::default();
+        history.record(simulation.vaccinate(1, 1, 1));
+        for (x, y, state) in [
+            (0, 0, CellState::Vaccinated),
+            (1, 1, CellState::Vaccinated),
+            (2, 2, CellState::Vaccinated),
+            (3, 3, CellState::Alive),
+            (1, 2, CellState::Infected),
+        ] {
+            assert_eq!(simulation.cells.get(x, y), state);
+        }
+        history.record(simulation.vaccinate(5, 5, 0));
+        assert_eq!(simulation.cells.get(5, 5), CellState::Vaccinated);
+        history.undo(&mut simulation);
+        history.undo(&mut simulation);
+        assert_eq!(simulation.cells, before);
+    }
+
+    #[test]
+    fn barriers_wall_off_the_infection() {
+        // an infected cell beside a line of living cells it always infects, with
+        // the cell between them either alive to pass it on or a barrier
+        let spread = |between: CellState| {
+            let mut cells = CellStates::new(10, 10);
+            for y in 4..7 {
+                cells.set(6, y, CellState::Alive);
+            }
+            cells.set(4, 5, CellState::Infected);
+            cells.set(5, 5, between);
+            let options = Options {
+                virulence: 0,
+                ..chance(1., 0)
+            };
+            let mut simulation = Simulation::new(cells, options);
+            // only living cells count as neighbours, not barriers
+            let live = if between == CellState::Alive { 3 } else { 2 };
+            assert_eq!(simulation.count_neighbors(6, 5), (live, 0));
+            simulation.step();
+            simulation.step();
+            let beyond = (0..10)
+                .flat_map(|y| (6..10).map(move |x| (x, y)))
+                .filter(|&(x, y)| simulation.cells.get(x, y) == CellState::Infected)
+                .count();
+            (simulation.cells.get(5, 5), beyond)
+        };
+        assert!(spread(CellState::Alive).1 > 0);
+        assert_eq!(spread(CellState::Barrier), (CellState::Barrier, 0));
+    }
+
+    // step lines of infected and living cells either side of a wall, which side a cell is on
+    // given by -1, 0 and 1, returning how many living cells caught the infection for each boundary
+    fn infected_through(
+        neighbourhood: Neighbourhood,
+        wall: CellState,
+        side: impl Fn(isize, isize) -> isize,
+    ) -> Vec<usize> {
+        let mut cells = CellStates::new(12, 12);
+        for y in 0..12 {
+            for x in 0..12 {
+                match side(x as isize, y as isize) {
+                    -1 => cells.set(x, y, CellState::Infected),
+                    0 => cells.set(x, y, wall),
+                    1 => cells.set(x, y, CellState::Alive),
+                    _ => (),
+                }
+            }
+        }
+        [Boundary::Dead, Boundary::Torus, Boundary::Unbounded]
+            .into_iter()
+            .map(|boundary| {
+                let options = Options {
+                    // living cells survive however crowded, so only the infection changes them
+                    living_rule: Rules::Range { min: 0, max: 24 },
+                    virulence: 0,
+                    neighbourhood: neighbourhood.clone(),
+                    boundary,
+                    ..chance(1., 0)
+                };
+                let mut simulation = Simulation::new(cells.clone(), options);
+                simulation.step();
+                (0..12)
+                    .flat_map(|y| (0..12).map(move |x| (x, y)))
+                    .filter(|&(x, y)| {
+                        side(x as isize, y as isize) == 1
+                            && simulation.cells.get(x, y) == CellState::Infected
+                    })
+                    .count()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diagonal_walls_hide_the_cells_beyond() {
+        let side = |x: isize, y: isize| (x + y - 10).clamp(-2, 2);
+        assert!(
+            infected_through(Neighbourhood::Moore(1), CellState::Dead, side)
+                .iter()
+                .all(|&n| n > 0)
+        );
+        assert_eq!(
+            infected_through(Neighbourhood::Moore(1), CellState::Barrier, side),
+            [0, 0, 0]
+        );
+        // a single barrier beside the corner leaves the diagonal neighbour in sight
+        let mut cells = CellStates::new(4, 4);
+        cells.set(1, 1, CellState::Infected);
+        cells.set(2, 1, CellState::Barrier);
+        let mut simulation = Simulation::new(cells, Options::default());
+        assert_eq!(simulation.count_neighbors(2, 2), (0, 1));
+        simulation.set(1, 2, CellState::Barrier);
+        assert_eq!(simulation.count_neighbors(2, 2), (0, 0));
+    }
+
+    #[test]
+    fn wide_neighbourhoods_can_not_see_over_walls() {
+        let line = |x: isize, _| (x - 5).clamp(-2, 2);
+        assert!(
+            infected_through(Neighbourhood::Moore(2), CellState::Dead, line)
+                .iter()
+                .all(|&n| n > 0)
+        );
+        assert_eq!(
+            infected_through(Neighbourhood::Moore(2), CellState::Barrier, line),
+            [0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn sight_lines_pass_through_the_cells_between() {
+        assert!(Sight::new((1, 0)).is_empty());
+        let diagonal = Sight::new((1, 1));
+        assert!(diagonal.cells.is_empty());
+        assert_eq!(diagonal.corners, [[(1, 0), (0, 1)]]);
+        assert_eq!(Sight::new((-2, 0)).cells, [(-1, 0)]);
+        assert_eq!(Sight::new((2, 1)).cells, [(1, 0), (1, 1)]);
+        let long = Sight::new((2, -2));
+        assert_eq!(long.cells, [(1, -1)]);
+        assert_eq!(long.corners.len(), 2);
+    }
+
+    #[test]
+    fn barriers_are_not_alive() {
+        let mut empty = simulation(&[]);
+        empty.set(3, 3, CellState::Barrier);
+        assert!(empty.is_extinct());
+        empty.options.boundary = Boundary::Unbounded;
+        empty.set_options(empty.options.clone());
+        assert!(empty.is_extinct());
+        empty.set(4, 4, CellState::Alive);
+        assert!(!empty.is_extinct());
+        // a barrier keeps its place however many living neighbours it has
+        let mut wall = simulation(&[(2, 2), (3, 2), (4, 2), (2, 3), (4, 3)]);
+        wall.set(3, 3, CellState::Barrier);
+        for _ in 0..3 {
+            wall.step();
+            assert_eq!(wall.cells.get(3, 3), CellState::Barrier);
+        }
+    }
+
+    // run with: cargo test --release -- --ignored --nocapture bench
+    #[test]
+    #[ignore]
+    fn bench_packed_step() {
+        use std::time::Instant;
+        let mut simulation = Simulation::new(scattered(1000, 1000), Options::default());
+        simulation.threads = 1;
+        simulation.step();
+        let start = Instant::now();
+        for _ in 0..5 {
+            simulation.step_cells(1);
+        }
+        let cells = start.elapsed() / 5;
+        let start = Instant::now();
+        for _ in 0..5 {
+            simulation.step();
+        }
+        let packed = start.elapsed() / 5;
+        println!(
+            "1000x1000 step: {:?} a cell at a time, {:?} packed ({:.1}x)",
+            cells,
+            packed,
+            cells.as_secs_f64() / packed.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn parallel_step_matches_serial_step() {
+        for options in [
+            Options::default(),
+            life(),
+            Options {
+                living_rule: Rules::Singles(vec![1, 3, 4]),
+                neighbourhood: Neighbourhood::Moore(2),
+                boundary: Boundary::KleinBottle,
+                ..Options::default()
+            },
+            Options {
+                layout: GridLayout::Hex,
+                boundary: Boundary::Torus,
+                ..Options::default()
+            },
+        ] {
+            // an odd number of rows leaves the last band shorter than the others
+            let mut serial = Simulation::new(scattered(150, 131), options.clone());
+            serial.threads = 1;
+            let mut parallel = Simulation::new(scattered(150, 131), options);
+            parallel.threads = 7;
+            for _ in 0..10 {
+                serial.step();
+                parallel.step();
+                assert!(serial.cells == parallel.cells);
+            }
+        }
+    }
+
+    // run with: cargo test --release -- --ignored --nocapture bench
+    #[test]
+    #[ignore]
+    fn bench_parallel_step() {
+        use std::time::Instant;
+        let mut simulation = Simulation::new(scattered(1000, 1000), Options::default());
+        let threads = simulation.threads;
+        simulation.threads = 1;
+        let start = Instant::now();
+        for _ in 0..5 {
+            simulation.step();
+        }
+        let serial = start.elapsed() / 5;
+        simulation.threads = threads;
+        let start = Instant::now();
+        for _ in 0..5 {
+            simulation.step();
+        }
+        let parallel = start.elapsed() / 5;
+        println!(
+            "1000x1000 step: {:?} on one thread, {:?} on {} threads ({:.1}x)",
+            serial,
+            parallel,
+            threads,
+            serial.as_secs_f64() / parallel.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn block_is_still() {
+        let block = [(10, 10), (10, 11), (11, 10), (11, 11)];
+        let mut simulation = simulation(&block);
+        simulation.step();
+        assert_eq!(alive(&simulation), block);
+    }
+
+    #[test]
+    fn blinker_oscillates() {
+        let horizontal = [(9, 10), (10, 10), (11, 10)];
+        let vertical = [(10, 9), (10, 10), (10, 11)];
+        let mut simulation = simulation(&horizontal);
+        simulation.step();
+        assert_eq!(alive(&simulation), vertical);
+        simulation.step();
+        assert_eq!(alive(&simulation), horizontal);
+    }
+
+    #[test]
+    fn blinker_oscillates_with_default_options() {
+        let horizontal = [(9, 10), (10, 10), (11, 10)];
+        let mut simulation = simulation(&horizontal);
+        simulation.options = Options::default();
+        simulation.step();
+        simulation.step();
+        assert_eq!(alive(&simulation), horizontal);
+    }
+
+    #[test]
+    fn toad_oscillates() {
+        let toad = [(11, 10), (12, 10), (13, 10), (10, 11), (11, 11), (12, 11)];
+        let mut simulation = simulation(&toad);
+        simulation.step();
+        assert_ne!(alive(&simulation), shifted(&toad, 0, 0));
+        simulation.step();
+        assert_eq!(alive(&simulation), shifted(&toad, 0, 0));
+    }
+
+    #[test]
+    fn glider_moves_diagonally() {
+        let glider = [(11, 10), (12, 11), (10, 12), (11, 12), (12, 12)];
+        let mut simulation = simulation(&glider);
+        for _ in 0..4 {
+            simulation.step();
+        }
+        assert_eq!(alive(&simulation), shifted(&glider, 1, 1));
+    }
+
+    #[test]
+    fn lightweight_spaceship_moves() {
+        let spaceship = [
+            (11, 10),
+            (14, 10),
+            (10, 11),
+            (10, 12),
+            (14, 12),
+            (10, 13),
+            (11, 13),
+            (12, 13),
+            (13, 13),
+        ];
+        let mut simulation = simulation(&spaceship);
+        for _ in 0..4 {
+            simulation.step();
+        }
+        let mut expected: Vec<_> = spaceship.iter().map(|&(x, y)| (x - 2, y)).collect();
+        expected.sort();
+        assert_eq!(alive(&simulation), expected);
+    }
+}
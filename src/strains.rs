@@ -0,0 +1,131 @@
+use crate::rng::Rng;
+
+// the most strains that can arise, the infection stops mutating once there are this many
+pub const MAX_STRAINS: usize = 256;
+// set on the strain of a cell whose infection mutated in the last step, on top of the strain
+// it mutated from, until the new strain is numbered once the whole grid has been stepped
+pub const MUTATED: u16 = 1 << 15;
+// the most a mutation can change the chance of a strain killing its host
+pub const LETHALITY_DRIFT: f32 = 0.1;
+
+// a variant of the infection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Strain {
+    // how easily the strain spreads and how few living neighbours its host needs, like
+    // the virulence in the options
+    pub virulence: u8,
+    // the chance a cell infected with the strain dies of it each generation
+    pub lethality: f32,
+    // the strain it mutated from, the first strain has none
+    pub parent: Option<u16>,
+}
+
+impl Strain {
+    // a strain a little more or less virulent and deadly than this one, picked by a roll
+    fn mutated(&self, id: u16, roll: u64, max_virulence: u8) -> Self {
+        let mut rng = Rng::new(roll);
+        let virulence =
+            (self.virulence as i16 + rng.below(3) as i16 - 1).clamp(0, max_virulence as i16);
+        let drift = (rng.next_f32() * 2. - 1.) * LETHALITY_DRIFT;
+        Self {
+            virulence: virulence as u8,
+            lethality: (self.lethality + drift).clamp(0., 1.),
+            parent: Some(id),
+        }
+    }
+}
+
+// every strain that has arisen, numbered in the order they did, the first is set by the options
+#[derive(Clone, Debug, PartialEq)]
+pub struct Strains {
+    strains: Vec<Strain>,
+}
+
+impl Strains {
+    pub fn new(virulence: u8, lethality: f32) -> Self {
+        Self {
+            strains: vec![Strain {
+                virulence,
+                lethality,
+                parent: None,
+            }],
+        }
+    }
+
+    // the strain with the given number, cells from before the strains were counted
+    // again fall back on the first
+    pub fn get(&self, id: u16) -> Strain {
+        self.strains
+            .get(id as usize)
+            .copied()
+            .unwrap_or(self.strains[0])
+    }
+
+    // the number of strains that have arisen, there's always at least the first
+    pub fn count(&self) -> usize {
+        self.strains.len()
+    }
+
+    // iterate over the number and traits of every strain
+    pub fn iter(&self) -> impl Iterator<Item = (u16, Strain)> + '_ {
+        (0..).zip(self.strains.iter().copied())
+    }
+
+    // follow changes to the options the first strain is set by
+    pub fn set_first(&mut self, virulence: u8, lethality: f32) {
+        self.strains[0].virulence = virulence;
+        self.strains[0].lethality = lethality;
+    }
+
+    // add a strain mutated from parent by a roll, returning its number,
+    // or the parent's once there's no room for any more
+    pub fn mutate(&mut self, parent: u16, roll: u64, max_virulence: u8) -> u16 {
+        if self.strains.len() >= MAX_STRAINS {
+            return parent;
+        }
+        let strain = self.get(parent).mutated(parent, roll, max_virulence);
+        self.strains.push(strain);
+        (self.strains.len() - 1) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutations_stay_close_to_their_parent() {
+        let mut strains = Strains::new(4, 0.5);
+        for roll in 0..100 {
+            let id = strains.mutate(0, roll, 8);
+            let strain = strains.get(id);
+            assert_eq!(strain.parent, Some(0));
+            assert!((3..=5).contains(&strain.virulence));
+            assert!((strain.lethality - 0.5).abs() <= LETHALITY_DRIFT);
+        }
+        // the same roll always gives the same strain
+        let mut again = Strains::new(4, 0.5);
+        let id = again.mutate(0, 0, 8);
+        assert_eq!(again.get(id), strains.get(1));
+        // traits are kept within their bounds
+        let mut strains = Strains::new(0, 0.);
+        for roll in 0..100 {
+            let id = strains.mutate(0, roll, 0);
+            let strain = strains.get(id);
+            assert_eq!(strain.virulence, 0);
+            assert!(strain.lethality >= 0.);
+        }
+    }
+
+    #[test]
+    fn strains_stop_arising_once_full() {
+        let mut strains = Strains::new(2, 0.);
+        for roll in 1..MAX_STRAINS as u64 {
+            assert_eq!(strains.mutate(0, roll, 8) as u64, roll);
+        }
+        assert_eq!(strains.mutate(7, 0, 8), 7);
+        assert_eq!(strains.count(), MAX_STRAINS);
+        // unknown strains fall back on the first
+        assert_eq!(strains.get(MAX_STRAINS as u16), strains.get(0));
+    }
+}
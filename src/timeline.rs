@@ -0,0 +1,201 @@
+use std::collections::VecDeque;
+
+use crate::{cells::CellStates, strains::Strains, world::World};
+
+// the most generations the timeline keeps before forgetting the oldest
+pub const TIMELINE_LENGTH: usize = 256;
+// the most memory the timeline takes up before forgetting the oldest, large grids
+// fill it long before they reach the length
+pub const TIMELINE_BYTES: usize = 64 << 20;
+
+// everything needed to put the simulation back the way it was at one generation
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub generation: u64,
+    pub cells: CellStates,
+    // the world behind an unbounded grid, none when the grid has edges
+    pub world: Option<World>,
+    pub origin: (i64, i64),
+    // the strains that had arisen, which the infected cells refer to by number
+    pub strains: Strains,
+}
+
+impl Snapshot {
+    // roughly how much memory the snapshot takes up
+    pub fn bytes(&self) -> usize {
+        let world = self
+            .world
+            .as_ref()
+            .map_or(0, |world| world.chunk_cells().map(CellStates::bytes).sum());
+        self.cells.bytes() + world
+    }
+}
+
+// the generations played so far, which can be stepped back through and branched from
+pub struct Timeline {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+    max_bytes: usize,
+    // the snapshot currently shown
+    position: usize,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self::new(TIMELINE_LENGTH, TIMELINE_BYTES)
+    }
+}
+
+impl Timeline {
+    // keep up to capacity generations, and only as many as fit in max_bytes,
+    // though the latest is always kept
+    pub fn new(capacity: usize, max_bytes: usize) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            capacity: capacity.max(1),
+            max_bytes,
+            position: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn get(&self, position: usize) -> Option<&Snapshot> {
+        self.snapshots.get(position)
+    }
+
+    // add a generation after the one currently shown, anything that came after it,
+    // or doesn't come before the new generation, is replaced by the new branch
+    pub fn record(&mut self, snapshot: Snapshot) {
+        self.snapshots.truncate(self.position + 1);
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|last| last.generation >= snapshot.generation)
+        {
+            self.snapshots.pop_back();
+        }
+        self.snapshots.push_back(snapshot);
+        // forget the oldest generations once the timeline is full
+        let mut bytes: usize = self.snapshots.iter().map(Snapshot::bytes).sum();
+        while self.snapshots.len() > 1
+            && (self.snapshots.len() > self.capacity || bytes > self.max_bytes)
+        {
+            bytes -= self.snapshots.pop_front().map_or(0, |s| s.bytes());
+        }
+        self.position = self.snapshots.len() - 1;
+    }
+
+    // move to a recorded generation, returning it so it can be restored
+    pub fn seek(&mut self, position: usize) -> Option<&Snapshot> {
+        if position < self.snapshots.len() {
+            self.position = position;
+        }
+        self.snapshots.get(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cells::CellState,
+        options::{Boundary, Options},
+        simulation::Simulation,
+    };
+
+    fn snapshot(generation: u64) -> Snapshot {
+        Snapshot {
+            generation,
+            cells: CellStates::new(4, 4),
+            world: None,
+            origin: (0, 0),
+            strains: Strains::new(0, 0.),
+        }
+    }
+
+    fn generations(timeline: &Timeline) -> Vec<u64> {
+        (0..timeline.len())
+            .map(|i| timeline.get(i).unwrap().generation)
+            .collect()
+    }
+
+    #[test]
+    fn oldest_generations_are_forgotten() {
+        let mut timeline = Timeline::new(3, TIMELINE_BYTES);
+        for generation in 0..5 {
+            timeline.record(snapshot(generation));
+        }
+        assert_eq!(generations(&timeline), [2, 3, 4]);
+        assert_eq!(timeline.position(), 2);
+        // or once they take up too much memory
+        let mut timeline = Timeline::new(TIMELINE_LENGTH, snapshot(0).bytes() * 2);
+        for generation in 0..5 {
+            timeline.record(snapshot(generation));
+        }
+        assert_eq!(generations(&timeline), [3, 4]);
+        // the latest generation is kept however large it is
+        let mut timeline = Timeline::new(TIMELINE_LENGTH, 0);
+        timeline.record(snapshot(0));
+        assert_eq!(generations(&timeline), [0]);
+    }
+
+    #[test]
+    fn recording_in_the_past_branches() {
+        let mut timeline = Timeline::default();
+        for generation in 0..5 {
+            timeline.record(snapshot(generation));
+        }
+        assert_eq!(timeline.seek(1).unwrap().generation, 1);
+        // an edit to the generation being shown replaces it and drops what came after
+        let mut edited = snapshot(1);
+        edited.cells.set(0, 0, CellState::Alive);
+        timeline.record(edited.clone());
+        assert_eq!(generations(&timeline), [0, 1]);
+        assert_eq!(timeline.get(1), Some(&edited));
+        timeline.record(snapshot(2));
+        assert_eq!(generations(&timeline), [0, 1, 2]);
+        // clearing the grid starts counting again, so everything is replaced
+        timeline.record(snapshot(0));
+        assert_eq!(generations(&timeline), [0]);
+        assert!(timeline.seek(5).is_none());
+        assert_eq!(timeline.position(), 0);
+    }
+
+    #[test]
+    fn simulation_is_restored_from_a_snapshot() {
+        let blinker = [(4, 5), (5, 5), (6, 5)];
+        for boundary in [Boundary::Dead, Boundary::Unbounded] {
+            let mut cells = CellStates::new(10, 10);
+            for (x, y) in blinker {
+                cells.set(x, y, CellState::Alive);
+            }
+            let options = Options {
+                virulence: 0,
+                boundary,
+                ..Options::default()
+            };
+            let mut simulation = Simulation::new(cells, options);
+            let start = simulation.snapshot();
+            assert!(simulation.matches(&start));
+            simulation.step();
+            assert!(!simulation.matches(&start));
+            let stepped = simulation.snapshot();
+            simulation.restore(&start);
+            assert!(simulation.matches(&start));
+            assert_eq!(simulation.snapshot(), start);
+            simulation.step();
+            assert_eq!(simulation.snapshot(), stepped);
+        }
+    }
+}
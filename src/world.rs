@@ -0,0 +1,568 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    cells::{Cell, CellState, CellStates},
+    simulation::{Neighbours, RuleTable},
+    strains::Strains,
+};
+
+// the width and height of the square chunks the unbounded grid is stored in
+pub const CHUNK_SIZE: usize = 64;
+
+// an unbounded grid that only stores the chunks holding living or infected cells,
+// keyed by the position of the chunk counted in chunks from the origin
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct World {
+    chunks: HashMap<(i64, i64), CellStates>,
+}
+
+// hash the chunks in order, skipping empty ones, so equal worlds always hash the same
+impl Hash for World {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut chunks: Vec<_> = self
+            .chunks
+            .iter()
+            .filter(|(_, cells)| !cells.is_empty())
+            .collect();
+        chunks.sort_unstable_by_key(|&(&position, _)| position);
+        chunks.hash(state);
+    }
+}
+
+impl World {
+    // copy a grid into the world with its top left cell at origin
+    pub fn from_cells(cells: &CellStates, origin: (i64, i64)) -> Self {
+        let mut world = Self::default();
+        for y in 0..cells.height() {
+            for x in 0..cells.width() {
+                let state = cells.get(x, y);
+                if state != CellState::Dead {
+                    world.set(origin.0 + x as i64, origin.1 + y as i64, state);
+                }
+            }
+        }
+        world
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> CellState {
+        let (chunk, (cx, cy)) = locate(x, y);
+        self.chunks
+            .get(&chunk)
+            .map_or(CellState::Dead, |cells| cells.get(cx, cy))
+    }
+
+    pub fn set(&mut self, x: i64, y: i64, state: CellState) {
+        let (chunk, (cx, cy)) = locate(x, y);
+        if state == CellState::Dead {
+            // nothing has to be stored for a dead cell in a missing chunk
+            if let Some(cells) = self.chunks.get_mut(&chunk) {
+                cells.set(cx, cy, state);
+                self.forget_if_empty(chunk);
+            }
+        } else {
+            self.chunks
+                .entry(chunk)
+                .or_insert_with(|| CellStates::new(CHUNK_SIZE, CHUNK_SIZE))
+                .set(cx, cy, state);
+        }
+    }
+
+    // everything stored about a cell, including its timer and strain
+    pub fn cell(&self, x: i64, y: i64) -> Cell {
+        let (chunk, (cx, cy)) = locate(x, y);
+        self.chunks
+            .get(&chunk)
+            .map_or(Cell::default(), |cells| cells.cell(cx, cy))
+    }
+
+    pub fn set_cell(&mut self, x: i64, y: i64, cell: Cell) {
+        let (chunk, (cx, cy)) = locate(x, y);
+        if cell == Cell::default() {
+            if let Some(cells) = self.chunks.get_mut(&chunk) {
+                cells.set_cell(cx, cy, cell);
+                self.forget_if_empty(chunk);
+            }
+        } else {
+            self.chunks
+                .entry(chunk)
+                .or_insert_with(|| CellStates::new(CHUNK_SIZE, CHUNK_SIZE))
+                .set_cell(cx, cy, cell);
+        }
+    }
+
+    // drop a chunk whose last cell died, so it's the same as a world that never stored it
+    fn forget_if_empty(&mut self, chunk: (i64, i64)) {
+        if self.chunks.get(&chunk).is_some_and(CellStates::is_empty) {
+            self.chunks.remove(&chunk);
+        }
+    }
+
+    pub fn clear_timers(&mut self) {
+        for cells in self.chunks.values_mut() {
+            cells.clear_timers();
+        }
+    }
+
+    // change the strain of a stored cell, every cell carrying one is stored
+    pub fn set_strain(&mut self, x: i64, y: i64, strain: u16) {
+        let (chunk, (cx, cy)) = locate(x, y);
+        if let Some(cells) = self.chunks.get_mut(&chunk) {
+            cells.set_strain(cx, cy, strain);
+        }
+    }
+
+    pub fn clear_strains(&mut self) {
+        for cells in self.chunks.values_mut() {
+            cells.clear_strains();
+        }
+    }
+
+    // iterate over the position and strain of every cell whose infection mutated in the last step
+    pub fn mutated(&self) -> impl Iterator<Item = ((i64, i64), u16)> + '_ {
+        self.chunks.iter().flat_map(|(&(chunk_x, chunk_y), cells)| {
+            let size = CHUNK_SIZE as i64;
+            cells.mutated().map(move |((x, y), strain)| {
+                (
+                    (chunk_x * size + x as i64, chunk_y * size + y as i64),
+                    strain,
+                )
+            })
+        })
+    }
+
+    // iterate over the stored chunks, in no particular order
+    pub fn chunk_cells(&self) -> impl Iterator<Item = &CellStates> + '_ {
+        self.chunks.values()
+    }
+
+    // the number of chunks being stored
+    pub fn chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // iterate over the state of every stored cell, in no particular order
+    pub fn states(&self) -> impl Iterator<Item = CellState> + '_ {
+        self.chunks.values().flat_map(|cells| cells.states())
+    }
+
+    // iterate over the position and state of every cell that isn't dead
+    pub fn cells(&self) -> impl Iterator<Item = ((i64, i64), CellState)> + '_ {
+        self.chunks.iter().flat_map(|(&(chunk_x, chunk_y), cells)| {
+            let size = CHUNK_SIZE as i64;
+            cells
+                .states()
+                .enumerate()
+                .filter(|&(_, state)| state != CellState::Dead)
+                .map(move |(i, state)| {
+                    let (x, y) = ((i % CHUNK_SIZE) as i64, (i / CHUNK_SIZE) as i64);
+                    ((chunk_x * size + x, chunk_y * size + y), state)
+                })
+        })
+    }
+
+    // whether any cell is infected
+    pub fn has_infected(&self) -> bool {
+        self.chunks.values().any(|cells| cells.has_infected())
+    }
+
+    // whether every cell is dead or a barrier
+    pub fn is_lifeless(&self) -> bool {
+        self.chunks.values().all(|cells| cells.is_lifeless())
+    }
+
+    // copy out the part of the world with its top left cell at origin
+    pub fn view(&self, origin: (i64, i64), width: usize, height: usize) -> CellStates {
+        let mut view = CellStates::new(width, height);
+        for (&(chunk_x, chunk_y), cells) in &self.chunks {
+            // the chunk's top left cell in view coordinates
+            let left = chunk_x * CHUNK_SIZE as i64 - origin.0;
+            let top = chunk_y * CHUNK_SIZE as i64 - origin.1;
+            let xs = left.max(0)..(left + CHUNK_SIZE as i64).min(width as i64);
+            let ys = top.max(0)..(top + CHUNK_SIZE as i64).min(height as i64);
+            for y in ys {
+                for x in xs.clone() {
+                    let (cx, cy) = ((x - left) as usize, (y - top) as usize);
+                    view.set_cell(x as usize, y as usize, cells.cell(cx, cy));
+                }
+            }
+        }
+        view
+    }
+
+    // work out the next generation, only visiting the stored chunks and the ones next to them
+    pub fn step(
+        &self,
+        table: &RuleTable,
+        strains: &Strains,
+        even_row: &[(isize, isize)],
+        odd_row: &[(isize, isize)],
+        generation: u64,
+        threads: usize,
+    ) -> Self {
+        // cells only come alive next to living or infected ones, so every other
+        // chunk stays empty, rules that bring lone dead cells to life would fill
+        // the whole plane and are never played unbounded
+        let mut active = HashSet::new();
+        for &(x, y) in self.chunks.keys() {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    active.insert((x + dx, y + dy));
+                }
+            }
+        }
+        let active: Vec<(i64, i64)> = active.into_iter().collect();
+        // share the chunks out between threads in the same way as the rows of a grid
+        let per_thread = active.len().div_ceil(threads.max(1)).max(1);
+        let stepped: Vec<((i64, i64), CellStates)> = if threads > 1 && active.len() > 1 {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = active
+                    .chunks(per_thread)
+                    .map(|keys| {
+                        scope.spawn(move || {
+                            self.step_chunks(keys, table, strains, even_row, odd_row, generation)
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().expect("Failed to step chunks"))
+                    .collect()
+            })
+        } else {
+            self.step_chunks(&active, table, strains, even_row, odd_row, generation)
+        };
+        Self {
+            chunks: stepped.into_iter().collect(),
+        }
+    }
+
+    // step the given chunks, leaving out any that end up empty
+    fn step_chunks(
+        &self,
+        keys: &[(i64, i64)],
+        table: &RuleTable,
+        strains: &Strains,
+        even_row: &[(isize, isize)],
+        odd_row: &[(isize, isize)],
+        generation: u64,
+    ) -> Vec<((i64, i64), CellStates)> {
+        let mut stepped = Vec::new();
+        for &key in keys {
+            let next = self.step_chunk(key, table, strains, even_row, odd_row, generation);
+            if !next.is_empty() {
+                stepped.push((key, next));
+            }
+        }
+        stepped
+    }
+
+    fn step_chunk(
+        &self,
+        (chunk_x, chunk_y): (i64, i64),
+        table: &RuleTable,
+        strains: &Strains,
+        even_row: &[(isize, isize)],
+        odd_row: &[(isize, isize)],
+        generation: u64,
+    ) -> CellStates {
+        let size = CHUNK_SIZE as isize;
+        // neighbourhoods are far smaller than a chunk, so every neighbour
+        // lies in this chunk or one of the eight around it
+        let mut around = [[None; 3]; 3];
+        for (dy, row) in around.iter_mut().enumerate() {
+            for (dx, chunk) in row.iter_mut().enumerate() {
+                *chunk = self
+                    .chunks
+                    .get(&(chunk_x + dx as i64 - 1, chunk_y + dy as i64 - 1));
+            }
+        }
+        // the chunk and position within it of a neighbour, if it's stored
+        let neighbour = |x: isize, y: isize| {
+            let chunk: Option<&CellStates> =
+                around[(y.div_euclid(size) + 1) as usize][(x.div_euclid(size) + 1) as usize];
+            chunk.map(|cells| {
+                (
+                    cells,
+                    x.rem_euclid(size) as usize,
+                    y.rem_euclid(size) as usize,
+                )
+            })
+        };
+        // barriers can only hide neighbours that are looked at through them
+        let walled = around
+            .iter()
+            .flatten()
+            .flatten()
+            .any(|cells| cells.has_barriers());
+        let hidden = |x: isize, y: isize, offset: (isize, isize)| {
+            walled
+                && table.sight(offset).is_some_and(|sight| {
+                    sight.blocked(|dx, dy| {
+                        neighbour(x + dx, y + dy)
+                            .is_some_and(|(cells, nx, ny)| cells.get(nx, ny) == CellState::Barrier)
+                    })
+                })
+        };
+        let mut next = CellStates::new(CHUNK_SIZE, CHUNK_SIZE);
+        for y in 0..size {
+            // chunks start on an even row, so rows keep their parity
+            let offsets = if y % 2 == 0 { even_row } else { odd_row };
+            for x in 0..size {
+                let (mut live_neighbors, mut infected_neighbors) = (0, 0);
+                for &(dx, dy) in offsets {
+                    if hidden(x, y, (dx, dy)) {
+                        continue;
+                    }
+                    if let Some((cells, nx, ny)) = neighbour(x + dx, y + dy) {
+                        match cells.get(nx, ny) {
+                            CellState::Alive
+                            | CellState::Recovered
+                            | CellState::Exposed
+                            | CellState::Vaccinated => live_neighbors += 1,
+                            CellState::Infected => infected_neighbors += 1,
+                            // barriers pass on neither life nor the infection
+                            CellState::Dead | CellState::Barrier => (),
+                        }
+                    }
+                }
+                let cell = around[1][1]
+                    .map_or_else(Default::default, |cells| cells.cell(x as usize, y as usize));
+                let position = (
+                    chunk_x * size as i64 + x as i64,
+                    chunk_y * size as i64 + y as i64,
+                );
+                // the strains of the infected neighbours, in the order of the offsets
+                let carried = offsets.iter().filter_map(|&(dx, dy)| {
+                    if hidden(x, y, (dx, dy)) {
+                        return None;
+                    }
+                    let (cells, nx, ny) = neighbour(x + dx, y + dy)?;
+                    (cells.get(nx, ny) == CellState::Infected).then(|| cells.strain(nx, ny))
+                });
+                let neighbours = Neighbours {
+                    live: live_neighbors,
+                    infected: infected_neighbors,
+                    source: table.source(
+                        cell.state,
+                        infected_neighbors,
+                        generation,
+                        position,
+                        carried,
+                    ),
+                };
+                let cell = table.next(strains, cell, neighbours, generation, position);
+                next.set_cell(x as usize, y as usize, cell);
+            }
+        }
+        next
+    }
+}
+
+// find the chunk a cell is stored in, and where in the chunk it is
+fn locate(x: i64, y: i64) -> ((i64, i64), (usize, usize)) {
+    let size = CHUNK_SIZE as i64;
+    (
+        (x.div_euclid(size), y.div_euclid(size)),
+        (x.rem_euclid(size) as usize, y.rem_euclid(size) as usize),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::scatter,
+        options::{
+            Boundary, GridLayout, Immunity, Infection, Options, Pathogen, Rules, Vaccination,
+        },
+        simulation::Simulation,
+    };
+
+    const GLIDER: [(usize, usize); 5] = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+
+    fn unbounded(width: usize, height: usize, alive: &[(usize, usize)]) -> Simulation {
+        let mut cells = CellStates::new(width, height);
+        for &(x, y) in alive {
+            cells.set(x, y, CellState::Alive);
+        }
+        Simulation::new(
+            cells,
+            Options {
+                virulence: 0,
+                boundary: Boundary::Unbounded,
+                ..Options::default()
+            },
+        )
+    }
+
+    fn alive(world: &World) -> Vec<(i64, i64)> {
+        let mut alive = Vec::new();
+        for (&(chunk_x, chunk_y), cells) in &world.chunks {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    if cells.get(x, y) == CellState::Alive {
+                        let size = CHUNK_SIZE as i64;
+                        alive.push((chunk_x * size + x as i64, chunk_y * size + y as i64));
+                    }
+                }
+            }
+        }
+        alive.sort_unstable();
+        alive
+    }
+
+    #[test]
+    fn cells_are_stored_in_chunks() {
+        let mut world = World::default();
+        world.set(-1, -1, CellState::Alive);
+        world.set(64, 0, CellState::Infected);
+        world.set(1000, 1000, CellState::Dead);
+        assert_eq!(world.chunks(), 2);
+        assert_eq!(world.get(-1, -1), CellState::Alive);
+        assert_eq!(world.get(64, 0), CellState::Infected);
+        assert_eq!(world.get(63, 0), CellState::Dead);
+        let view = world.view((-2, -2), 3, 3);
+        assert_eq!(view.get(1, 1), CellState::Alive);
+        assert_eq!(view.states().filter(|&s| s != CellState::Dead).count(), 1);
+    }
+
+    #[test]
+    fn glider_leaves_the_grid_behind() {
+        let mut simulation = unbounded(10, 10, &GLIDER);
+        for _ in 0..400 {
+            simulation.step();
+        }
+        // the glider moved 100 cells down and to the right, far beyond the grid
+        let mut expected: Vec<(i64, i64)> = GLIDER
+            .iter()
+            .map(|&(x, y)| (x as i64 + 100, y as i64 + 100))
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(alive(&simulation.world), expected);
+        assert!(simulation.cells.is_empty());
+        // empty chunks are dropped along the way
+        assert!(simulation.world.chunks() <= 4);
+    }
+
+    #[test]
+    fn world_matches_a_large_grid() {
+        for options in [
+            Options::default(),
+            Options {
+                layout: GridLayout::Hex,
+                ..Options::default()
+            },
+            // rolls for infection are made at the same positions in the world
+            Options {
+                infection: Infection::Chance {
+                    probability: 0.5,
+                    seed: 1,
+                },
+                ..Options::default()
+            },
+            // timers carry over between chunks as cells recover and lose their immunity
+            Options {
+                recovery: 2,
+                immunity: Immunity::Waning(3),
+                ..Options::default()
+            },
+            Options {
+                incubation: 3,
+                recovery: 2,
+                ..Options::default()
+            },
+            // as are the rolls for vaccines and campaigns
+            Options {
+                vaccination: Vaccination {
+                    efficacy: 0.5,
+                    rate: 0.05,
+                    start: 5,
+                    seed: 2,
+                },
+                ..Options::default()
+            },
+            // and strains arise in the same order
+            Options {
+                infection: Infection::Chance {
+                    probability: 0.5,
+                    seed: 3,
+                },
+                pathogen: Pathogen {
+                    lethality: 0.2,
+                    mutation: 0.3,
+                    seed: 4,
+                },
+                ..Options::default()
+            },
+        ] {
+            // a pattern in the middle of a grid large enough that nothing reaches its edges
+            let mut cells = CellStates::new(300, 300);
+            scatter(&mut cells, 120..180, 120..180, 99, &CellState::ALL[..3]);
+            let mut bounded = Simulation::new(cells.clone(), options.clone());
+            let mut world = Simulation::new(
+                cells,
+                Options {
+                    boundary: Boundary::Unbounded,
+                    ..options
+                },
+            );
+            world.threads = 3;
+            for _ in 0..20 {
+                bounded.step();
+                world.step();
+                assert_eq!(bounded.cells, world.cells);
+                assert_eq!(bounded.strains(), world.strains());
+            }
+        }
+    }
+
+    #[test]
+    fn births_from_nothing_keep_their_edges() {
+        let seeds = Options {
+            dead_rule: Rules::Singles(vec![0, 3]),
+            virulence: 0,
+            boundary: Boundary::Unbounded,
+            ..Options::default()
+        };
+        assert!(!Simulation::new(CellStates::new(10, 10), seeds.clone()).unbounded());
+        let mut simulation = unbounded(10, 10, &GLIDER);
+        simulation.set_options(seeds);
+        assert!(!simulation.unbounded());
+        assert_eq!(simulation.cells.get(1, 0), CellState::Alive);
+        for _ in 0..5 {
+            simulation.step();
+        }
+        assert_eq!(simulation.world.chunks(), 0);
+    }
+
+    #[test]
+    fn emptied_chunks_are_forgotten() {
+        let mut simulation = unbounded(10, 10, &GLIDER);
+        simulation.scroll_to(200, 200);
+        let shown = simulation.snapshot();
+        simulation.edit(0, 0, CellState::Alive);
+        assert_eq!(simulation.world.chunks(), 2);
+        simulation.edit(0, 0, CellState::Dead);
+        assert_eq!(simulation.world.chunks(), 1);
+        assert!(simulation.matches(&shown));
+    }
+
+    #[test]
+    fn scrolling_moves_the_view() {
+        let mut simulation = unbounded(10, 10, &[(0, 0)]);
+        assert_eq!(simulation.scroll_to(-1, 4), (-1, 0));
+        assert_eq!(simulation.origin, (-1, 0));
+        assert_eq!(simulation.cells.get(1, 0), CellState::Alive);
+        assert_eq!(simulation.scroll_to(3, 12), (0, 3));
+        assert_eq!(simulation.cells.get(1, 0), CellState::Dead);
+        simulation.set(0, 0, CellState::Infected);
+        assert_eq!(simulation.world.get(-1, 3), CellState::Infected);
+        // the view stays put when the grid has edges
+        simulation.set_options(Options::default());
+        assert_eq!(simulation.scroll_to(-5, 0), (0, 0));
+        assert_eq!(simulation.world.chunks(), 0);
+    }
+}